@@ -0,0 +1,171 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use serde::Serialize;
+use toml::Value;
+
+use crate::config::merge_tables;
+use crate::util::log_info;
+use crate::{run, take_log_messages, Config, ConfigFile, Result, RunReport};
+
+/// One run of a batch with its own settings.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// The `name` of the job in the config, or its input directory.
+    pub name: String,
+    pub config: Config,
+}
+
+/// What happened in [run_batch].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchReport {
+    /// One report per job, in the order of the jobs.
+    pub jobs: Vec<JobReport>,
+}
+
+impl BatchReport {
+    /// The number of jobs that failed.
+    pub fn failed_count(&self) -> usize {
+        self.jobs.iter().filter(|job| job.error.is_some()).count()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    pub name: String,
+    /// The report of the run, `None` if it failed.
+    pub report: Option<RunReport>,
+    /// Why the job failed.
+    pub error: Option<String>,
+    /// The warnings logged during the job.
+    pub warnings: Vec<String>,
+    /// The errors logged during the job that did not stop it.
+    pub errors: Vec<String>,
+    pub seconds: f32,
+}
+
+///
+/// Returns the jobs of the config. The settings of each job are the settings of the config with
+/// the ones given in the job's table replaced, tables like `id_map` are merged key by key. A job can
+/// have a `name` to tell it apart in the report.
+///
+pub fn batch_jobs(config: &Config) -> Result<Vec<Job>> {
+    let mut global = config.clone();
+    global.jobs.clear();
+    let global = Value::try_from(ConfigFile::from(global))?;
+
+    let mut jobs = Vec::new();
+
+    for (index, table) in config.jobs.iter().enumerate() {
+        let mut table = table.clone();
+
+        let name = match table.remove("name") {
+            Some(Value::String(name)) => name,
+            Some(_) => return Err(format!("The name of job {} is not a string.", index + 1).into()),
+            None => match table.get("input_directory") {
+                Some(Value::String(input_directory)) => input_directory.clone(),
+                _ => format!("job {}", index + 1),
+            },
+        };
+
+        let mut settings = global.clone();
+        merge_tables(&mut settings, table);
+
+        let config_file: ConfigFile = settings
+            .try_into()
+            .map_err(|err| format!("Invalid settings in job '{}': {}", name, err))?;
+        let mut job_config = Config::from(config_file);
+        // Opening an explorer window for every job would get in the way.
+        job_config.open_output_directory = false;
+
+        jobs.push(Job { name, config: job_config });
+    }
+
+    Ok(jobs)
+}
+
+///
+/// Runs every job, one after the other or, when `parallel` is set, as many at a time as there are
+/// processors. A failing job doesn't stop the others.
+///
+pub fn run_batch(jobs: &[Job], parallel: bool) -> BatchReport {
+    let worker_count = if parallel {
+        thread::available_parallelism().map_or(1, |count| count.get()).min(jobs.len())
+    } else {
+        1
+    };
+
+    let next_job = AtomicUsize::new(0);
+    let mut reports: Vec<Option<JobReport>> = vec![None; jobs.len()];
+
+    // Every job runs on a worker thread, so the log messages of each job can be told apart.
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..worker_count)
+            .map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let index = next_job.fetch_add(1, Ordering::Relaxed);
+                    match jobs.get(index) {
+                        Some(job) => done.push((index, run_job(job))),
+                        None => return done,
+                    }
+                }
+            }))
+            .collect();
+
+        for worker in workers {
+            // Jobs catch their own panics, so this only loses reports if the bookkeeping itself
+            // fails. Those jobs are reported as failed below.
+            for (index, report) in worker.join().unwrap_or_default() {
+                reports[index] = Some(report);
+            }
+        }
+    });
+
+    BatchReport {
+        jobs: reports
+            .into_iter()
+            .zip(jobs)
+            .map(|(report, job)| report.unwrap_or_else(|| JobReport {
+                name: job.name.clone(),
+                report: None,
+                error: Some("The job was interrupted by a crash.".to_owned()),
+                warnings: Vec::new(),
+                errors: Vec::new(),
+                seconds: 0.0,
+            }))
+            .collect(),
+    }
+}
+
+/// Runs one job. A panic fails only this job, the worker goes on with the next one.
+fn run_job(job: &Job) -> JobReport {
+    log_info!("Starting job '{}'.", job.name);
+    let start_time = Instant::now();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(&job.config, None)));
+    let log = take_log_messages();
+
+    let (report, error) = match result {
+        Ok(Ok(report)) => (Some(report), None),
+        Ok(Err(err)) => (None, Some(err.to_string())),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown error".to_owned());
+            (None, Some(format!("The job crashed: {}", message)))
+        }
+    };
+
+    JobReport {
+        name: job.name.clone(),
+        report,
+        error,
+        warnings: log.warnings,
+        errors: log.errors,
+        seconds: start_time.elapsed().as_secs_f32(),
+    }
+}
@@ -1,7 +1,10 @@
 use std::{env, fs};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use crate::Result;
+use crate::{validate_config, ConfigError, Result};
 
 const DEFAULT_CONFIG_FILE_NAME: &str = "texture_stacker.toml";
 
@@ -9,10 +12,53 @@ const DEFAULT_CONFIG_FILE_NAME: &str = "texture_stacker.toml";
 pub struct Config {
     pub keep_mask_alpha: bool,
     pub output_masks: bool,
+    pub id_map: Option<IdMapConfig>,
+    pub mesh_masks: Option<MeshMaskConfig>,
+    pub generate_mipmaps: bool,
+    pub output_layered: bool,
+    pub gltf_merge: Option<GltfMergeConfig>,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    pub optimize_png: bool,
     pub suffixes: Vec<String>,
+    /// Splits the texture sets into several combined outputs. All sets are combined into
+    /// `output_texture_name` if empty.
+    pub output_groups: Vec<OutputGroup>,
+    /// Texture set names in compositing order: the first set is the background and later sets are
+    /// drawn over earlier ones where they overlap. Unlisted sets follow in alphabetical order.
+    pub set_order: Vec<String>,
+    /// Texture set name -> priority, 0 if not given. Sets with a higher priority are drawn over
+    /// sets with a lower one, regardless of `set_order`.
+    pub set_priority: BTreeMap<String, i32>,
+    /// Suffix -> other suffixes that are used for the same texture type in input file names.
+    /// Outputs always use the suffix from `suffixes`.
+    pub suffix_aliases: BTreeMap<String, Vec<String>>,
+    /// How set names and texture types are read from file names. By default the name is split on
+    /// the last `_`. See the sample config for the syntax.
+    pub naming_pattern: Option<String>,
     pub output_texture_name: String,
     pub input_directory: String,
+    /// More directories to gather texture sets from, merged with the sets of the input directory.
+    pub additional_input_directories: Vec<String>,
+    /// How many levels of subdirectories are scanned for textures, 0 only scans the top level.
+    pub scan_depth: u32,
+    /// A [crate::Manifest] listing the texture sets to combine, used instead of scanning the input
+    /// directories. Relative to the input directory, which defaults to the manifest's directory.
+    pub manifest: Option<String>,
+    /// Glob patterns of texture set names to use. All sets are used if empty.
+    pub include_sets: Vec<String>,
+    /// Glob patterns of texture set names to leave out.
+    pub exclude_sets: Vec<String>,
+    /// Glob patterns of texture files to use. All files are used if empty.
+    pub include_files: Vec<String>,
+    /// Glob patterns of texture files to leave out.
+    pub exclude_files: Vec<String>,
     pub output_directory: Option<String>,
+    /// Open the output directory in the file explorer when done (Windows only).
+    pub open_output_directory: bool,
+    /// The jobs of a batch, see [crate::batch_jobs]. Each is a table of settings that replace the
+    /// ones of this config for that job.
+    pub jobs: Vec<toml::value::Table>,
 }
 
 impl Default for Config {
@@ -20,69 +66,380 @@ impl Default for Config {
         Self {
             keep_mask_alpha: false,
             output_masks: false,
+            id_map: None,
+            mesh_masks: None,
+            generate_mipmaps: false,
+            output_layered: false,
+            gltf_merge: None,
+            png_compression: PngCompression::default(),
+            png_filter: PngFilter::default(),
+            optimize_png: false,
             suffixes: vec![
                 "_D".to_owned(),
                 "_N".to_owned(),
                 "_E".to_owned(),
                 "_M".to_owned(),
             ],
+            output_groups: Vec::new(),
+            set_order: Vec::new(),
+            set_priority: BTreeMap::new(),
+            suffix_aliases: BTreeMap::new(),
+            naming_pattern: None,
             output_texture_name: "T_Combined".to_owned(),
             input_directory: String::new(),
+            additional_input_directories: Vec::new(),
+            scan_depth: 0,
+            manifest: None,
+            include_sets: Vec::new(),
+            exclude_sets: Vec::new(),
+            include_files: Vec::new(),
+            exclude_files: Vec::new(),
             output_directory: None,
+            open_output_directory: true,
+            jobs: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct ConfigFile {
+///
+/// A material ID texture used as the source of the set masks instead of the alpha channels. A pixel
+/// belongs to a texture set if its color matches the color given for the set.
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdMapConfig {
+    /// The ID map texture, relative to the input directory.
+    pub texture: String,
+    /// Texture set name -> color, as `#rrggbb`.
     #[serde(default)]
-    pub suffixes: Vec<String>,
+    pub sets: BTreeMap<String, String>,
+    /// A legend file with set colors, like the one written by `output_masks`. Colors in `sets`
+    /// take precedence.
+    pub legend: Option<String>,
+    /// Maximum per-channel difference for a pixel to match a set color.
+    #[serde(default)]
+    pub tolerance: u8,
+}
 
+///
+/// A mesh used as the source of the set masks instead of the alpha channels. The UV triangles of
+/// each material are rasterized into the mask of the texture set with the same name.
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MeshMaskConfig {
+    /// An OBJ, glTF or GLB file, relative to the input directory.
+    pub mesh: String,
+    /// Cover every pixel touched by a triangle instead of only the ones whose center is inside, so
+    /// the edges of UV islands are fully covered.
     #[serde(default)]
-    pub output_masks: bool,
+    pub conservative: bool,
+    /// Which UV set (`TEXCOORD_n`) to use for glTF meshes.
+    #[serde(default)]
+    pub uv_set: usize,
+}
 
+///
+/// Texture sets combined into their own output textures. A set belongs to the first group where it
+/// matches any of the rules.
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputGroup {
+    /// The name of the combined textures of this group, used instead of `output_texture_name`.
+    pub output_texture_name: String,
+    /// Sets whose name starts with this prefix.
+    pub prefix: Option<String>,
+    /// Sets whose name matches this regular expression.
+    pub pattern: Option<String>,
+    /// Sets with one of these names.
     #[serde(default)]
-    pub keep_mask_alpha: bool,
+    pub sets: Vec<String>,
+}
 
-    pub output_texture_name: Option<String>,
-    pub input_directory: Option<String>,
+/// A material texture slot in a glTF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GltfSlot {
+    BaseColor,
+    Normal,
+    Emissive,
+    MetallicRoughness,
+    Occlusion,
 }
 
-impl Into<Config> for ConfigFile {
-    fn into(self) -> Config {
-        let mut config = Config {
-            suffixes: self.suffixes,
-            output_masks: self.output_masks,
-            keep_mask_alpha: self.keep_mask_alpha,
-            ..Config::default()
-        };
+///
+/// Rewrites a glTF or GLB file after stacking so that all primitives using the materials of the
+/// stacked texture sets use a single new material with the combined textures instead.
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GltfMergeConfig {
+    /// The glTF or GLB file to rewrite, relative to the input directory.
+    pub input: String,
+    /// Where to write the result, relative to the output directory. Defaults to the name of the
+    /// input file.
+    pub output: Option<String>,
+    /// Name of the new material. Defaults to the output texture name.
+    pub material_name: Option<String>,
+    /// Texture suffix -> material slot. Suffixes without a slot are not used in the material.
+    #[serde(default = "GltfMergeConfig::default_slots")]
+    pub slots: BTreeMap<String, GltfSlot>,
+}
 
-        if let Some(output_texture_name) = self.output_texture_name {
-            config.output_texture_name = output_texture_name;
-        }
+impl GltfMergeConfig {
+    /// Slots for the default suffixes.
+    pub fn default_slots() -> BTreeMap<String, GltfSlot> {
+        BTreeMap::from([
+            ("_D".to_owned(), GltfSlot::BaseColor),
+            ("_N".to_owned(), GltfSlot::Normal),
+            ("_E".to_owned(), GltfSlot::Emissive),
+            ("_M".to_owned(), GltfSlot::MetallicRoughness),
+        ])
+    }
+}
 
-        if let Some(input_directory) = self.input_directory {
-            config.input_directory = input_directory;
-        }
+/// Options for splitting a combined texture back into per-set textures.
+#[derive(Debug, Clone, Default)]
+pub struct UnstackConfig {
+    /// The combined texture to split.
+    pub combined_texture: String,
+    /// Pairs of texture set name and mask file. White pixels in a mask belong to the set.
+    pub set_masks: Vec<(String, String)>,
+    /// An ID map and its legend, used instead of or in addition to `set_masks`.
+    pub id_map: Option<(String, String)>,
+    /// The suffix used for the output files. Taken from the combined texture's name if not given,
+    /// using `naming_pattern` and `suffixes` like [Config].
+    pub suffix: Option<String>,
+    pub naming_pattern: Option<String>,
+    pub suffixes: Vec<String>,
+    /// Defaults to the directory of the combined texture.
+    pub output_directory: Option<String>,
+}
+
+/// Compression level used when writing PNG files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+/// Filter strategy used when writing PNG files. `Adaptive` picks the best filter for each row.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PngFilter {
+    #[default]
+    Adaptive,
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+}
+
+impl FromStr for PngCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_snake_case(s, "fast, default, best")
+    }
+}
+
+impl FromStr for PngFilter {
+    type Err = String;
 
-        config
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_snake_case(s, "adaptive, none, sub, up, avg, paeth")
+    }
+}
+
+/// Parses a unit enum variant by the name it has in the config file.
+fn parse_snake_case<T: DeserializeOwned>(s: &str, expected: &str) -> std::result::Result<T, String> {
+    toml::Value::String(s.to_owned())
+        .try_into()
+        .map_err(|_| format!("'{}' is not one of: {}.", s, expected))
+}
+
+/// The version of the config file format, see [ConfigFile::version].
+pub const CONFIG_VERSION: u32 = 2;
+
+///
+/// The contents of a config file, with every setting of [Config]. Settings missing from the file
+/// have their default value.
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    /// The version of the format the file was written in. Files without a version are version 1,
+    /// older files are migrated when read.
+    pub version: u32,
+
+    pub suffixes: Vec<String>,
+    pub output_groups: Vec<OutputGroup>,
+    pub set_order: Vec<String>,
+    pub set_priority: BTreeMap<String, i32>,
+    pub suffix_aliases: BTreeMap<String, Vec<String>>,
+    pub naming_pattern: Option<String>,
+    pub output_masks: bool,
+    pub keep_mask_alpha: bool,
+    pub id_map: Option<IdMapConfig>,
+    pub mesh_masks: Option<MeshMaskConfig>,
+    pub generate_mipmaps: bool,
+    pub output_layered: bool,
+    pub gltf_merge: Option<GltfMergeConfig>,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    pub optimize_png: bool,
+    pub output_texture_name: String,
+    pub input_directory: String,
+    pub additional_input_directories: Vec<String>,
+    pub scan_depth: u32,
+    pub manifest: Option<String>,
+    pub include_sets: Vec<String>,
+    pub exclude_sets: Vec<String>,
+    pub include_files: Vec<String>,
+    pub exclude_files: Vec<String>,
+    pub output_directory: Option<String>,
+    pub open_output_directory: bool,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<toml::value::Table>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Config::default().into()
+    }
+}
+
+impl From<ConfigFile> for Config {
+    fn from(config_file: ConfigFile) -> Self {
+        Self {
+            suffixes: config_file.suffixes,
+            output_groups: config_file.output_groups,
+            set_order: config_file.set_order,
+            set_priority: config_file.set_priority,
+            suffix_aliases: config_file.suffix_aliases,
+            naming_pattern: config_file.naming_pattern,
+            output_masks: config_file.output_masks,
+            keep_mask_alpha: config_file.keep_mask_alpha,
+            id_map: config_file.id_map,
+            mesh_masks: config_file.mesh_masks,
+            generate_mipmaps: config_file.generate_mipmaps,
+            output_layered: config_file.output_layered,
+            gltf_merge: config_file.gltf_merge,
+            png_compression: config_file.png_compression,
+            png_filter: config_file.png_filter,
+            optimize_png: config_file.optimize_png,
+            output_texture_name: config_file.output_texture_name,
+            input_directory: config_file.input_directory,
+            additional_input_directories: config_file.additional_input_directories,
+            scan_depth: config_file.scan_depth,
+            manifest: config_file.manifest,
+            include_sets: config_file.include_sets,
+            exclude_sets: config_file.exclude_sets,
+            include_files: config_file.include_files,
+            exclude_files: config_file.exclude_files,
+            output_directory: config_file.output_directory,
+            open_output_directory: config_file.open_output_directory,
+            jobs: config_file.jobs,
+        }
     }
 }
 
 impl From<Config> for ConfigFile {
     fn from(config: Config) -> Self {
         Self {
+            version: CONFIG_VERSION,
             suffixes: config.suffixes,
+            output_groups: config.output_groups,
+            set_order: config.set_order,
+            set_priority: config.set_priority,
+            suffix_aliases: config.suffix_aliases,
+            naming_pattern: config.naming_pattern,
             output_masks: config.output_masks,
             keep_mask_alpha: config.keep_mask_alpha,
-            output_texture_name: Some(config.output_texture_name),
-            input_directory: Some(config.input_directory),
+            id_map: config.id_map,
+            mesh_masks: config.mesh_masks,
+            generate_mipmaps: config.generate_mipmaps,
+            output_layered: config.output_layered,
+            gltf_merge: config.gltf_merge,
+            png_compression: config.png_compression,
+            png_filter: config.png_filter,
+            optimize_png: config.optimize_png,
+            output_texture_name: config.output_texture_name,
+            input_directory: config.input_directory,
+            additional_input_directories: config.additional_input_directories,
+            scan_depth: config.scan_depth,
+            manifest: config.manifest,
+            include_sets: config.include_sets,
+            exclude_sets: config.exclude_sets,
+            include_files: config.include_files,
+            exclude_files: config.exclude_files,
+            output_directory: config.output_directory,
+            open_output_directory: config.open_output_directory,
+            jobs: config.jobs,
+        }
+    }
+}
+
+///
+/// Brings the contents of a config file written in an older version of the format up to
+/// [CONFIG_VERSION].
+///
+/// - Version 1 (no `version` field): an empty `suffixes` list left the tool without texture types,
+///   it now means the default suffixes.
+///
+fn migrate_config(table: &mut toml::value::Table) -> Result<()> {
+    let version = config_version(table.get("version"))?;
+
+    if version < 2 {
+        let has_no_suffixes = matches!(table.get("suffixes"), Some(toml::Value::Array(suffixes)) if suffixes.is_empty());
+        if has_no_suffixes {
+            table.remove("suffixes");
         }
     }
+
+    table.insert("version".to_owned(), toml::Value::Integer(CONFIG_VERSION as i64));
+    Ok(())
+}
+
+/// Returns the version of a config file from its `version` setting.
+pub(crate) fn config_version(version: Option<&toml::Value>) -> Result<i64> {
+    let version = match version {
+        None => 1,
+        Some(toml::Value::Integer(version)) if *version >= 1 => *version,
+        Some(version) => return Err(format!("Invalid config file version {}.", version).into()),
+    };
+
+    if version > CONFIG_VERSION as i64 {
+        return Err(format!(
+            "The config file has version {}, which is newer than the supported version {}.",
+            version,
+            CONFIG_VERSION).into());
+    }
+
+    Ok(version)
 }
 
+/// Validates, parses and migrates the contents of the config file at `path`.
+fn parse_config_table(raw: &str, path: &Path) -> Result<toml::value::Table> {
+    let issues = validate_config(raw);
+    if !issues.is_empty() {
+        return Err(ConfigError { path: path.to_path_buf(), issues }.into());
+    }
+
+    let mut table = toml::from_str(raw)?;
+    migrate_config(&mut table)?;
+    Ok(table)
+}
+
+/// Reads the config files found by [discover_config] without an explicit path or input directory.
 pub fn read_config_file() -> Result<ConfigFile> {
-    read_config_from_path(get_default_config_path()?)
+    let discovered = discover_config(None, None)?;
+    if discovered.sources.is_empty() {
+        return Err("Config file not found".into());
+    }
+    Ok(discovered.config)
 }
 
 pub fn write_config_file(config: &ConfigFile) -> Result<()> {
@@ -93,7 +450,7 @@ pub fn read_config_from_path(path: impl AsRef<Path>) -> Result<ConfigFile> {
     let path = path.as_ref();
     if path.is_file() {
         let raw = fs::read_to_string(path)?;
-        let config_file: ConfigFile = toml::from_str(&raw)?;
+        let config_file = toml::Value::Table(parse_config_table(&raw, path)?).try_into()?;
         Ok(config_file)
     } else {
         Err("Config file not found".into())
@@ -101,11 +458,152 @@ pub fn read_config_from_path(path: impl AsRef<Path>) -> Result<ConfigFile> {
 }
 
 pub fn write_config_to_path(path: impl AsRef<Path>, config: &ConfigFile) -> Result<()> {
-    let raw = toml::to_string(config)?;
+    // Going through a value writes the tables after the plain settings, as TOML requires.
+    let raw = toml::to_string(&toml::Value::try_from(config)?)?;
     fs::write(path, raw)?;
     Ok(())
 }
 
+///
+/// Replaces the given settings in the config file at `path`, keeping its other settings, or creates
+/// the file with only these settings. Comments in the file are not kept.
+///
+pub fn update_config_file(path: impl AsRef<Path>, settings: toml::value::Table) -> Result<()> {
+    let path = path.as_ref();
+    let table = if path.is_file() {
+        parse_config_table(&fs::read_to_string(path)?, path)?
+    } else {
+        toml::value::Table::new()
+    };
+
+    let mut config = toml::Value::Table(table);
+    merge_tables(&mut config, settings);
+    if let toml::Value::Table(table) = &mut config {
+        table.insert("version".to_owned(), toml::Value::Integer(CONFIG_VERSION as i64));
+    }
+
+    fs::write(path, toml::to_string(&config)?)?;
+    Ok(())
+}
+
+/// The config file next to the executable, used when no other config file is found.
+pub fn default_config_path() -> Result<PathBuf> {
+    get_default_config_path()
+}
+
+/// A config combined from every config file found, see [discover_config].
+#[derive(Debug)]
+pub struct DiscoveredConfig {
+    pub config: ConfigFile,
+    /// The files the config was read from, the one that takes precedence first. Empty if no
+    /// config file was found, the config then has the default settings.
+    pub sources: Vec<PathBuf>,
+}
+
+///
+/// Looks for config files in this order: `explicit_path`, then a `texture_stacker.toml` in the input
+/// directory, the current directory, the user config directory and next to the executable. Every
+/// file found is used, settings of earlier files replace the ones of later files and tables like
+/// `id_map` are merged key by key.
+///
+/// Without `input_directory`, the input directory set by the other files is searched.
+///
+pub fn discover_config(explicit_path: Option<&Path>, input_directory: Option<&Path>) -> Result<DiscoveredConfig> {
+    if let Some(path) = explicit_path {
+        if !path.is_file() {
+            return Err(format!("The config file '{}' does not exist.", path.to_string_lossy()).into());
+        }
+    }
+
+    let mut candidates: Vec<PathBuf> = explicit_path.map(Path::to_path_buf).into_iter().collect();
+    candidates.extend(env::current_dir().ok().map(|directory| directory.join(DEFAULT_CONFIG_FILE_NAME)));
+    candidates.extend(get_user_config_directory().map(|directory| directory.join(DEFAULT_CONFIG_FILE_NAME)));
+    candidates.extend(get_default_config_path().ok());
+
+    let mut layers = read_config_layers(&candidates)?;
+
+    // The input directory comes after the explicit config, which may be the one that sets it.
+    let input_directory = input_directory.map(Path::to_path_buf).or_else(|| {
+        layers.iter().find_map(|(_, layer)| match layer.get("input_directory") {
+            Some(toml::Value::String(directory)) if !directory.is_empty() => Some(PathBuf::from(directory)),
+            _ => None,
+        })
+    });
+    if let Some(input_directory) = input_directory {
+        let position = usize::from(explicit_path.is_some());
+        candidates.insert(position, input_directory.join(DEFAULT_CONFIG_FILE_NAME));
+        layers = read_config_layers(&candidates)?;
+    }
+
+    // Apply the files that take precedence last.
+    let mut merged = toml::Value::Table(toml::value::Table::new());
+    for (_, layer) in layers.iter().rev() {
+        merge_tables(&mut merged, layer.clone());
+    }
+
+    let sources = layers.into_iter().map(|(path, _)| path).collect();
+    let config = merged.try_into()?;
+
+    Ok(DiscoveredConfig { config, sources })
+}
+
+/// Reads every candidate file that exists, skipping files that were already read through another path.
+fn read_config_layers(candidates: &[PathBuf]) -> Result<Vec<(PathBuf, toml::value::Table)>> {
+    let mut read: Vec<PathBuf> = Vec::new();
+    let mut layers = Vec::new();
+
+    for path in candidates {
+        let canonical = match path.canonicalize() {
+            Ok(canonical) if canonical.is_file() => canonical,
+            _ => continue,
+        };
+        if read.contains(&canonical) {
+            continue;
+        }
+        read.push(canonical);
+
+        let raw = fs::read_to_string(path)?;
+        let layer = parse_config_table(&raw, path)?;
+        layers.push((path.clone(), layer));
+    }
+
+    Ok(layers)
+}
+
+/// Replaces the values of `target` with the ones of `overrides`, merging nested tables.
+pub(crate) fn merge_tables(target: &mut toml::Value, overrides: toml::value::Table) {
+    let target = match target {
+        toml::Value::Table(target) => target,
+        _ => return,
+    };
+
+    for (key, value) in overrides {
+        match (target.get_mut(&key), value) {
+            (Some(existing @ toml::Value::Table(_)), toml::Value::Table(value)) => merge_tables(existing, value),
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The directory for the config of the current user, e.g. `%APPDATA%\texture_stacker` on Windows
+/// and `~/.config/texture_stacker` on Linux.
+fn get_user_config_directory() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+
+    directory.map(|directory| directory.join("texture_stacker"))
+}
+
 fn get_default_config_path() -> Result<PathBuf> {
     let mut path = PathBuf::new();
     path.push(env::current_exe()?);
@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Serialize;
+use toml::Spanned;
+
+use crate::config::config_version;
+use crate::ConfigFile;
+
+/// The settings of [ConfigFile].
+const CONFIG_KEYS: &[&str] = &[
+    "version",
+    "suffixes",
+    "output_groups",
+    "set_order",
+    "set_priority",
+    "suffix_aliases",
+    "naming_pattern",
+    "output_masks",
+    "keep_mask_alpha",
+    "id_map",
+    "mesh_masks",
+    "generate_mipmaps",
+    "output_layered",
+    "gltf_merge",
+    "png_compression",
+    "png_filter",
+    "optimize_png",
+    "output_texture_name",
+    "input_directory",
+    "additional_input_directories",
+    "scan_depth",
+    "manifest",
+    "include_sets",
+    "exclude_sets",
+    "include_files",
+    "exclude_files",
+    "output_directory",
+    "open_output_directory",
+    "jobs",
+];
+
+/// The settings of [ConfigFile] a job can't change.
+const NOT_JOB_KEYS: &[&str] = &["version", "jobs"];
+
+const ID_MAP_KEYS: &[&str] = &["texture", "sets", "legend", "tolerance"];
+const MESH_MASK_KEYS: &[&str] = &["mesh", "conservative", "uv_set"];
+const GLTF_MERGE_KEYS: &[&str] = &["input", "output", "material_name", "slots"];
+const OUTPUT_GROUP_KEYS: &[&str] = &["output_texture_name", "prefix", "pattern", "sets"];
+
+/// Settings that can't be used together, and why.
+const CONFLICTS: &[(&str, &str, &str)] = &[
+    ("id_map", "mesh_masks", "only one of them can be the source of the set masks"),
+    ("manifest", "additional_input_directories", "the manifest lists the textures instead"),
+    ("manifest", "naming_pattern", "the manifest lists the textures instead"),
+];
+
+/// Characters that can't be part of a file name on every platform.
+const INVALID_NAME_CHARACTERS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// A problem found in a config file by [validate_config].
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigIssue {
+    /// The line the problem is on, starting at 1. `None` if it isn't known.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+/// The problems of an invalid config file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.to_string_lossy();
+        write!(f, "Invalid config file '{}':", path)?;
+        for issue in &self.issues {
+            match (issue.line, issue.column) {
+                (Some(line), Some(column)) => write!(f, "\n  {}:{}:{}: {}", path, line, column, issue.message)?,
+                (Some(line), None) => write!(f, "\n  {}:{}: {}", path, line, issue.message)?,
+                _ => write!(f, "\n  {}: {}", path, issue.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigError {}
+
+///
+/// Checks the contents of a config file and returns the problems found, each with where in the
+/// file it is:
+///
+/// - TOML syntax errors and settings with the wrong type,
+/// - unknown settings, also in tables like `id_map` and in `jobs`,
+/// - empty or duplicate suffixes,
+/// - output texture names that can't be used in a file name,
+/// - settings that can't be used together, like `id_map` and `mesh_masks`.
+///
+/// A file may leave out settings that another config file sets, so missing settings are not a
+/// problem.
+///
+pub fn validate_config(raw: &str) -> Vec<ConfigIssue> {
+    let locator = Locator { raw };
+
+    let value: toml::Value = match toml::from_str(raw) {
+        Ok(value) => value,
+        Err(err) => return vec![locator.toml_issue(&err)],
+    };
+
+    let document = toml::from_str::<Node>(raw).ok();
+    let find_key = |key: &str| match &document {
+        Some(Node::Table(table)) => table.iter().find(|(name, _)| name.get_ref() == key).map(|(name, _)| name.start()),
+        _ => None,
+    };
+
+    // Settings of a newer version can't be checked.
+    if let Err(err) = config_version(value.get("version")) {
+        return vec![locator.issue(find_key("version"), err.to_string())];
+    }
+
+    let mut issues = Vec::new();
+
+    // Only the files using an unsupported type have no document, the type check below finds those.
+    if let Some(Node::Table(table)) = &document {
+        let mut checker = Checker {
+            locator: &locator,
+            issues: &mut issues,
+        };
+        checker.check_settings(table, "", CONFIG_KEYS);
+
+        if let Some((_, Node::Array(jobs))) = find(table, "jobs") {
+            // A job can also have a name.
+            let job_keys: Vec<&str> = CONFIG_KEYS
+                .iter()
+                .filter(|key| !NOT_JOB_KEYS.contains(key))
+                .chain(&["name"])
+                .copied()
+                .collect();
+
+            for (index, job) in jobs.iter().enumerate() {
+                if let Node::Table(job) = job {
+                    let prefix = match find(job, "name") {
+                        Some((_, Node::String(name))) => format!("jobs.{}.", name),
+                        _ => format!("jobs[{}].", index + 1),
+                    };
+                    checker.check_settings(job, &prefix, &job_keys);
+                }
+            }
+        }
+    }
+
+    // Tables like `id_map` may be completed by another config file, so missing fields are fine.
+    if let Err(err) = toml::from_str::<ConfigFile>(raw) {
+        if !err.to_string().starts_with("missing field") {
+            issues.push(locator.toml_issue(&err));
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+    issues
+}
+
+/// Turns byte offsets into the file into lines and columns.
+struct Locator<'a> {
+    raw: &'a str,
+}
+
+impl Locator<'_> {
+    fn issue(&self, offset: Option<usize>, message: String) -> ConfigIssue {
+        let (line, column) = match offset {
+            Some(offset) => {
+                let before = &self.raw[..offset.min(self.raw.len())];
+                let line_start = before.rfind('\n').map_or(0, |position| position + 1);
+                (Some(before.matches('\n').count() + 1), Some(before[line_start..].chars().count() + 1))
+            }
+            None => (None, None),
+        };
+        ConfigIssue { line, column, message }
+    }
+
+    fn toml_issue(&self, err: &toml::de::Error) -> ConfigIssue {
+        let message = err.to_string();
+        match err.line_col() {
+            Some((line, column)) => ConfigIssue {
+                line: Some(line + 1),
+                column: Some(column + 1),
+                // The location is part of the message.
+                message: message.rsplit_once(" at line ").map_or(message.clone(), |(message, _)| message.to_owned()),
+            },
+            None => ConfigIssue {
+                line: None,
+                column: None,
+                message,
+            },
+        }
+    }
+}
+
+struct Checker<'a> {
+    locator: &'a Locator<'a>,
+    issues: &'a mut Vec<ConfigIssue>,
+}
+
+impl Checker<'_> {
+    /// Checks a table with the settings of a config file or of a job, `prefix` names the job.
+    fn check_settings(&mut self, table: &Table, prefix: &str, keys: &[&str]) {
+        self.check_keys(table, prefix, keys);
+
+        for (key, nested_keys) in [("id_map", ID_MAP_KEYS), ("mesh_masks", MESH_MASK_KEYS), ("gltf_merge", GLTF_MERGE_KEYS)] {
+            if let Some((_, Node::Table(nested))) = find(table, key) {
+                self.check_keys(nested, &format!("{}{}.", prefix, key), nested_keys);
+            }
+        }
+
+        if let Some((key, Node::Array(suffixes))) = find(table, "suffixes") {
+            self.check_suffixes(suffixes, key.start());
+        }
+
+        if let Some((key, Node::String(name))) = find(table, "output_texture_name") {
+            self.check_output_texture_name(name, key.start());
+        }
+
+        if let Some((key, Node::Array(groups))) = find(table, "output_groups") {
+            for group in groups {
+                if let Node::Table(group) = group {
+                    self.check_keys(group, &format!("{}output_groups.", prefix), OUTPUT_GROUP_KEYS);
+                    if let Some((name_key, Node::String(name))) = find(group, "output_texture_name") {
+                        self.check_output_texture_name(name, name_key.start());
+                    }
+                } else {
+                    self.add(key.start(), "Every entry of 'output_groups' must be a table.".to_owned());
+                }
+            }
+        }
+
+        for (first, second, reason) in CONFLICTS {
+            if let (Some((_, first_value)), Some((key, second_value))) = (find(table, first), find(table, second)) {
+                if first_value.is_set() && second_value.is_set() {
+                    self.add(
+                        key.start(),
+                        format!("{}'{}' can't be used together with '{}', {}.", prefix, second, first, reason));
+                }
+            }
+        }
+    }
+
+    fn check_keys(&mut self, table: &Table, prefix: &str, keys: &[&str]) {
+        for (key, _) in table {
+            if !keys.contains(&key.get_ref().as_str()) {
+                self.add(key.start(), format!("Unknown setting '{}{}'.", prefix, key.get_ref()));
+            }
+        }
+    }
+
+    fn check_suffixes(&mut self, suffixes: &[Node], offset: usize) {
+        let mut seen: Vec<String> = Vec::new();
+        for suffix in suffixes {
+            let suffix = match suffix {
+                Node::String(suffix) => suffix,
+                _ => continue,
+            };
+
+            if suffix.trim().is_empty() {
+                self.add(offset, "'suffixes' contains an empty suffix.".to_owned());
+            } else if seen.contains(&suffix.to_lowercase()) {
+                // Suffixes are matched case-insensitively.
+                self.add(offset, format!("The suffix '{}' is listed more than once.", suffix));
+            } else {
+                seen.push(suffix.to_lowercase());
+            }
+        }
+    }
+
+    fn check_output_texture_name(&mut self, name: &str, offset: usize) {
+        if let Some(character) = name.chars().find(|character| INVALID_NAME_CHARACTERS.contains(character) || character.is_control()) {
+            self.add(
+                offset,
+                format!("The output texture name '{}' contains the character '{}', which can't be used in file names.", name, character.escape_debug()));
+        }
+    }
+
+    fn add(&mut self, offset: usize, message: String) {
+        self.issues.push(self.locator.issue(Some(offset), message));
+    }
+}
+
+type Table = Vec<(Spanned<String>, Node)>;
+
+/// A TOML value that remembers where its keys are in the file.
+enum Node {
+    Table(Table),
+    Array(Vec<Node>),
+    String(String),
+    Other,
+}
+
+impl Node {
+    /// Whether the setting does something, empty lists are the same as leaving them out.
+    fn is_set(&self) -> bool {
+        !matches!(self, Node::Array(values) if values.is_empty())
+    }
+}
+
+fn find<'a>(table: &'a Table, key: &str) -> Option<(&'a Spanned<String>, &'a Node)> {
+    table.iter().find(|(name, _)| name.get_ref() == key).map(|(name, value)| (name, value))
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a TOML value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> std::result::Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> std::result::Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> std::result::Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> std::result::Result<Node, E> {
+        Ok(Node::Other)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Node, E> {
+        Ok(Node::String(value.to_owned()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Node, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Node::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Node::Table(entries))
+    }
+}
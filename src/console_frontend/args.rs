@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use texture_stacker::Config;
+
+pub(crate) const USAGE: &str = "\
+Usage: texture_stacker_console [command] [options] [input directory]
+       texture_stacker_console unstack <combined texture> <output directory> (<set name>=<mask file>... | --id-map <ID map> <legend file>)
+       texture_stacker_console detect <input directory> [--write]
+       texture_stacker_console batch [options] [--parallel]
+       texture_stacker_console config [--config <file>] [input directory]
+
+Commands:
+  combine                      Combine the texture sets, the default
+  inspect                      List the texture sets found, their textures and formats
+  validate                     Run every check of combine without writing any files
+  masks                        Only write the ID map of the set masks
+  batch                        Run the jobs of the config file, options apply to every job
+  config                       Show which config files are used and the settings they add up to
+
+Options:
+  -c, --config <file>          Config file that takes precedence over the ones found, see the README
+  -o, --output <directory>     Output directory, defaults to a 'Combined' directory in the input directory
+  -n, --name <name>            Name of the combined textures
+  -s, --suffixes <a,b,...>     Texture type suffixes, the first one is used as the mask
+      --keep-alpha             Keep the alpha channel of the mask texture
+      --no-keep-alpha          Remove the alpha channel of the mask texture
+      --masks                  Also write an ID map of the set masks
+      --mipmaps                Also write mipmaps
+      --layered                Also write a layered PSD per texture type
+      --compression <level>    PNG compression: fast, default or best
+      --filter <filter>        PNG filter: adaptive, none, sub, up, avg or paeth
+      --optimize               Try harder to make the PNG files small
+      --scan-depth <n>         How many levels of subdirectories to scan for textures
+      --add-input <directory>  Another directory to gather texture sets from, can be repeated
+      --manifest <file>        A manifest listing the texture sets to combine
+      --naming <pattern>       How set names and texture types are read from file names
+      --no-open                Don't open the output directory when done
+      --no-prompt              Never ask for input, for use in scripts
+      --watch                  Keep combining whenever the input textures change
+      --parallel               Run several batch jobs at the same time
+      --json                   Print the result as JSON, including warnings, errors and the files written
+  -h, --help                   Show this help
+
+Exit codes: 0 on success, 1 if the command failed, 2 for invalid arguments or config.";
+
+/// Options of the combine, inspect, validate and masks commands. Options that are not given keep the value from the
+/// config file.
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    pub help: bool,
+    pub config_path: Option<String>,
+    pub input_directory: Option<String>,
+    pub output_directory: Option<String>,
+    pub output_texture_name: Option<String>,
+    pub suffixes: Option<Vec<String>>,
+    pub keep_mask_alpha: Option<bool>,
+    pub output_masks: bool,
+    pub generate_mipmaps: bool,
+    pub output_layered: bool,
+    pub png_compression: Option<String>,
+    pub png_filter: Option<String>,
+    pub optimize_png: bool,
+    pub scan_depth: Option<u32>,
+    pub additional_input_directories: Vec<String>,
+    pub manifest: Option<String>,
+    pub naming_pattern: Option<String>,
+    pub no_open: bool,
+    pub watch: bool,
+    pub parallel: bool,
+    pub json: bool,
+}
+
+impl Args {
+    /// Parses the arguments, without the program name and `--no-prompt`.
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            // Values can be given as `--name value` or `--name=value`.
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || -> Result<String, String> {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| format!("Missing value for '{}'.", flag))
+            };
+
+            match flag {
+                "-h" | "--help" => parsed.help = true,
+                "-c" | "--config" => parsed.config_path = Some(value()?),
+                "-o" | "--output" => parsed.output_directory = Some(value()?),
+                "-n" | "--name" => parsed.output_texture_name = Some(value()?),
+                "-s" | "--suffixes" => {
+                    parsed.suffixes = Some(value()?
+                        .split(',')
+                        .map(|suffix| suffix.trim().to_owned())
+                        .filter(|suffix| !suffix.is_empty())
+                        .collect());
+                }
+                "--keep-alpha" => parsed.keep_mask_alpha = Some(true),
+                "--no-keep-alpha" => parsed.keep_mask_alpha = Some(false),
+                "--masks" => parsed.output_masks = true,
+                "--mipmaps" => parsed.generate_mipmaps = true,
+                "--layered" => parsed.output_layered = true,
+                "--compression" => parsed.png_compression = Some(value()?),
+                "--filter" => parsed.png_filter = Some(value()?),
+                "--optimize" => parsed.optimize_png = true,
+                "--scan-depth" => parsed.scan_depth = Some(parse_value(flag, &value()?)?),
+                "--add-input" => parsed.additional_input_directories.push(value()?),
+                "--manifest" => parsed.manifest = Some(value()?),
+                "--naming" => parsed.naming_pattern = Some(value()?),
+                "--no-open" => parsed.no_open = true,
+                "--watch" => parsed.watch = true,
+                "--parallel" => parsed.parallel = true,
+                "--json" => parsed.json = true,
+                _ if flag.starts_with('-') => return Err(format!("Unknown option '{}'.", flag)),
+                _ if parsed.input_directory.is_none() => parsed.input_directory = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument '{}'.", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Overrides the settings of the config with the ones given on the command line.
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(input_directory) = &self.input_directory {
+            config.input_directory = input_directory.clone();
+        }
+        if let Some(output_directory) = &self.output_directory {
+            config.output_directory = Some(output_directory.clone());
+        }
+        if let Some(output_texture_name) = &self.output_texture_name {
+            config.output_texture_name = sanitize_output_texture_name(output_texture_name);
+        }
+        if let Some(suffixes) = &self.suffixes {
+            config.suffixes = suffixes.clone();
+        }
+        if let Some(keep_mask_alpha) = self.keep_mask_alpha {
+            config.keep_mask_alpha = keep_mask_alpha;
+        }
+        if let Some(compression) = &self.png_compression {
+            config.png_compression = parse_value("--compression", compression)?;
+        }
+        if let Some(filter) = &self.png_filter {
+            config.png_filter = parse_value("--filter", filter)?;
+        }
+        if let Some(scan_depth) = self.scan_depth {
+            config.scan_depth = scan_depth;
+        }
+        if let Some(manifest) = &self.manifest {
+            config.manifest = Some(manifest.clone());
+        }
+        if let Some(naming_pattern) = &self.naming_pattern {
+            config.naming_pattern = Some(naming_pattern.clone());
+        }
+
+        config.output_masks |= self.output_masks;
+        config.generate_mipmaps |= self.generate_mipmaps;
+        config.output_layered |= self.output_layered;
+        config.optimize_png |= self.optimize_png;
+        config.additional_input_directories.extend(self.additional_input_directories.iter().cloned());
+        if self.no_open {
+            config.open_output_directory = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Makes sure the output texture name does not contain path separators.
+pub(crate) fn sanitize_output_texture_name(name: &str) -> String {
+    name.trim_matches(&['/', '\\'] as &[char])
+        .replace(['/', '\\'], "_")
+}
+
+fn parse_value<T>(flag: &str, value: &str) -> Result<T, String>
+    where
+        T: FromStr,
+        T::Err: ToString,
+{
+    value
+        .parse()
+        .map_err(|err: T::Err| format!("Invalid value for '{}': {}", flag, err.to_string()))
+}
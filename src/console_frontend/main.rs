@@ -1,14 +1,23 @@
-use std::{env, io};
+use std::{env, fmt, io};
 use std::error::Error;
 use std::io::Write;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
-use texture_stacker::Config;
+use texture_stacker::{Config, ConfigError, DiscoveredConfig, Inspection, ProgressHandler, RunReport, UnstackConfig, WatchOptions};
 
-mod interop;
+use crate::args::{sanitize_output_texture_name, Args, USAGE};
+use crate::progress_bar::ConsoleProgress;
 
-pub(crate) type Result<T> = std::result::Result<T, Box<dyn Error>>;
+macro_rules! log_warn {
+    ($fmt:literal) => {
+        std::eprintln!(std::concat!("\x1b[33m[WARN]\x1b[0m ", $fmt));
+    };
+    ($fmt:literal, $($arg:tt)*) => {
+        std::eprintln!(std::concat!("\x1b[33m[WARN]\x1b[0m ", $fmt), $($arg)*);
+    };
+}
 
 macro_rules! log_error {
     ($fmt:literal) => {
@@ -19,78 +28,513 @@ macro_rules! log_error {
     };
 }
 
+mod args;
+mod interop;
+mod progress_bar;
+
+pub(crate) type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Exit code when combining failed.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid arguments or config.
+const EXIT_USAGE: i32 = 2;
+
+/// Set by `--no-prompt`, never wait for user input.
+static NO_PROMPT: AtomicBool = AtomicBool::new(false);
+
+/// Set while running a batch, whose jobs recover from panics.
+static CATCHES_PANICS: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--json`, the result is printed as JSON instead of log messages.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// The commands that combine texture sets, sharing the same options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Write the combined textures.
+    Combine,
+    /// List the texture sets that would be combined.
+    Inspect,
+    /// Run every check of `Combine` without writing anything.
+    Validate,
+    /// Write only the ID map of the set masks.
+    Masks,
+}
+
+/// An error caused by the arguments or config given by the user.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for UsageError {}
+
 fn main() {
     interop::enable_virtual_terminal_processing();
     setup_panic_handler();
 
-    println!("Hello, world!");
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-    if let Err(err) = run() {
-        log_error!("Critical error: {}", err);
-    }
+    // `--no-prompt` applies to every command.
+    let arg_count = args.len();
+    args.retain(|arg| arg != "--no-prompt");
+    NO_PROMPT.store(args.len() != arg_count, Ordering::Relaxed);
+
+    let result = match args.first().map(String::as_str) {
+        Some("unstack") => run_unstack(&args[1..]),
+        Some("detect") => run_detect(&args[1..]),
+        Some("batch") => run_batch(&args[1..]),
+        Some("config") => run_config(&args[1..]),
+        Some("combine") => run(Command::Combine, &args[1..]),
+        Some("inspect") => run(Command::Inspect, &args[1..]),
+        Some("validate") => run(Command::Validate, &args[1..]),
+        Some("masks") => run(Command::Masks, &args[1..]),
+        _ => run(Command::Combine, &args),
+    };
+
+    let exit_code = match result {
+        Ok(()) => 0,
+        // The error is part of the JSON document.
+        Err(err) if JSON_OUTPUT.load(Ordering::Relaxed) => {
+            if is_usage_error(err.as_ref()) { EXIT_USAGE } else { EXIT_FAILURE }
+        }
+        Err(err) if is_usage_error(err.as_ref()) => {
+            log_error!("{}", err);
+            EXIT_USAGE
+        }
+        Err(err) => {
+            log_error!("Critical error: {}", err);
+            EXIT_FAILURE
+        }
+    };
 
-    prompt_for_string("Press enter to close this window...").unwrap();
+    exit_blocking(exit_code);
 }
 
-fn run() -> std::result::Result<(), Box<dyn Error>> {
-    let input_directory = get_input_directory()?;
+/// Whether the error is about the arguments or config rather than the textures.
+fn is_usage_error(err: &(dyn Error + 'static)) -> bool {
+    err.is::<UsageError>() || err.is::<ConfigError>()
+}
 
-    if !Path::new(&input_directory).is_dir() {
-        log_error!("The specified input directory is not valid.");
-        exit_blocking(1);
+/// Makes stdout a single JSON document: prompts and log messages would end up in it.
+fn enable_json_output() {
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+    NO_PROMPT.store(true, Ordering::Relaxed);
+    texture_stacker::set_log_printing(false);
+}
+
+fn is_interactive() -> bool {
+    !NO_PROMPT.load(Ordering::Relaxed)
+}
+
+fn run(command: Command, args: &[String]) -> Result<()> {
+    let args = Args::parse(args).map_err(|err| UsageError(format!("{}\n\n{}", err, USAGE)))?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
     }
 
-    let output_texture_name = get_output_texture_name()?;
-    let keep_mask_alpha = get_keep_mask_alpha()?;
+    if args.watch {
+        if command != Command::Combine || args.json {
+            return Err(UsageError("--watch only works with the combine command and without --json.".to_owned()).into());
+        }
+        return run_watch(&load_config(command, &args)?);
+    }
+
+    if args.json {
+        enable_json_output();
+    }
+
+    let start_time = Instant::now();
+    let result = load_config(command, &args).and_then(|config| {
+        // Only the commands that write files take long enough for a progress bar.
+        if args.json || !matches!(command, Command::Combine | Command::Masks) {
+            return run_command(command, &config, None);
+        }
+
+        let progress = ConsoleProgress::new();
+        let result = run_command(command, &config, Some(progress.handler()));
+        progress.finish(match &result {
+            Ok(CommandOutput::Report(report)) => Some(report),
+            _ => None,
+        });
+        result
+    });
+
+    if args.json {
+        print_json(command, &result, start_time.elapsed().as_secs_f32());
+        return result.map(|_| ());
+    }
+
+    match result? {
+        CommandOutput::Report(report) if command == Command::Validate => {
+            let set_count: usize = report.outputs.iter().map(|output| output.sets.len()).sum();
+            println!("The config is valid, {} texture sets would be combined into {} outputs.", set_count, report.outputs.len());
+        }
+        CommandOutput::Report(_) => {}
+        CommandOutput::Inspection(inspection) => {
+            print_inspection(&inspection);
+            return Ok(());
+        }
+    }
+    println!("Finished in {} s", start_time.elapsed().as_secs_f32());
+
+    Ok(())
+}
+
+/// The result of one of the commands sharing the combine options.
+enum CommandOutput {
+    Report(RunReport),
+    Inspection(Inspection),
+}
+
+fn run_command(command: Command, config: &Config, progress_handler: Option<ProgressHandler>) -> Result<CommandOutput> {
+    Ok(match command {
+        Command::Combine => CommandOutput::Report(texture_stacker::run(config, progress_handler)?),
+        Command::Inspect => CommandOutput::Inspection(texture_stacker::inspect(config)?),
+        Command::Validate => CommandOutput::Report(texture_stacker::validate(config)?),
+        Command::Masks => CommandOutput::Report(texture_stacker::write_masks(config, progress_handler)?),
+    })
+}
+
+/// Prints the result of a command as a single JSON document, along with the warnings and errors
+/// logged while running it.
+fn print_json(command: Command, result: &Result<CommandOutput>, seconds: f32) {
+    let mut log = texture_stacker::take_log_messages();
+    if let Err(err) = result {
+        log.errors.push(err.to_string());
+    }
 
-    // Load config file.
-    let mut config: Config = match texture_stacker::read_config_file() {
-        Ok(config) => config.into(),
+    let mut document = serde_json::json!({
+        "command": format!("{:?}", command).to_lowercase(),
+        "success": result.is_ok(),
+        "seconds": seconds,
+        "warnings": log.warnings,
+        "errors": log.errors,
+    });
+    match result {
+        Ok(CommandOutput::Report(report)) => document["report"] = serde_json::json!(report),
+        Ok(CommandOutput::Inspection(inspection)) => document["inspection"] = serde_json::json!(inspection),
         Err(err) => {
-            log_error!("Error loading config file \"{}\", using defaults.", err);
-            Config::default()
+            if let Some(config_error) = err.downcast_ref::<ConfigError>() {
+                document["config_error"] = serde_json::json!(config_error);
+            }
         }
-    };
+    }
+
+    println!("{}", serde_json::to_string_pretty(&document).unwrap());
+}
+
+/// Combines the texture sets whenever they change. Watching never ends by itself, only when the
+/// process is stopped with Ctrl+C or the watch fails.
+fn run_watch(config: &Config) -> Result<()> {
+    let never_stop = AtomicBool::new(false);
+
+    // Only the summary of each rebuild is printed, every file written would bury it.
+    texture_stacker::set_log_printing(false);
+    println!("Watching for changes, press Ctrl+C to stop.");
+
+    texture_stacker::watch(config, &WatchOptions::default(), &never_stop, None, |rebuild| {
+        let log = texture_stacker::take_log_messages();
+        for warning in &log.warnings {
+            log_warn!("{}", warning);
+        }
+        for error in &log.errors {
+            log_error!("{}", error);
+        }
+
+        if rebuild.result.is_err() {
+            log_error!("{}", rebuild);
+        } else {
+            println!("{}", rebuild);
+        }
+    })
+}
+
+/// Loads the config file, applies the options and asks for the settings the command needs that
+/// were not given.
+fn load_config(command: Command, args: &Args) -> Result<Config> {
+    let mut config = read_config(args)?;
 
     // Apply options from user.
-    config.output_texture_name = output_texture_name;
-    config.keep_mask_alpha = keep_mask_alpha;
+    args.apply(&mut config).map_err(UsageError)?;
+
+    // A config file may already set the input directory, a manifest works without one.
+    if config.input_directory.is_empty() && config.manifest.is_none() && is_interactive() {
+        config.input_directory = prompt_for_input_directory()?;
+    }
+
+    if config.manifest.is_none() && !Path::new(&config.input_directory).is_dir() {
+        return Err(UsageError("The specified input directory is not valid.".to_owned()).into());
+    }
+
+    // Only the commands that write files need a name.
+    let writes_files = matches!(command, Command::Combine | Command::Masks);
+    if args.output_texture_name.is_none() && writes_files && is_interactive() {
+        config.output_texture_name = prompt_for_output_texture_name()?;
+    }
+
+    if args.keep_mask_alpha.is_none() && command == Command::Combine && is_interactive() {
+        config.keep_mask_alpha = prompt_for_keep_mask_alpha()?;
+    }
 
     // Validate settings.
     if config.suffixes.is_empty() {
-        log_error!("No suffixes specified in config.");
-        exit_blocking(1);
+        return Err(UsageError("No suffixes specified in config.".to_owned()).into());
+    }
+
+    Ok(config)
+}
+
+/// Reads the config files for the options, see [texture_stacker::discover_config].
+fn discover_config(args: &Args) -> Result<DiscoveredConfig> {
+    texture_stacker::discover_config(
+        args.config_path.as_deref().map(Path::new),
+        args.input_directory.as_deref().map(Path::new))
+}
+
+/// Reads the config files for the options. Without config files the defaults are used, an invalid
+/// config file is an error.
+fn read_config(args: &Args) -> Result<Config> {
+    match discover_config(args) {
+        Ok(discovered) => Ok(discovered.config.into()),
+        // Kept as it is, so the JSON output can list the problems.
+        Err(err) if err.is::<ConfigError>() => Err(err),
+        Err(err) => Err(UsageError(err.to_string()).into()),
+    }
+}
+
+/// Usage: `config [--config <file>] [input directory]`
+///
+/// Prints which config files are used, in order of precedence, and the settings they add up to.
+fn run_config(args: &[String]) -> Result<()> {
+    let args = Args::parse(args).map_err(|err| UsageError(format!("{}\n\n{}", err, USAGE)))?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let discovered = discover_config(&args).map_err(|err| UsageError(err.to_string()))?;
+    if discovered.sources.is_empty() {
+        println!("No config file found, using the defaults.");
+    } else {
+        println!("Config files, earlier ones take precedence:");
+        for source in &discovered.sources {
+            println!("  {}", source.to_string_lossy());
+        }
+    }
+
+    println!();
+    println!("{}", toml::to_string(&toml::Value::try_from(&discovered.config)?)?);
+
+    Ok(())
+}
+
+/// Usage: `batch [options]`
+///
+/// Runs the jobs of the config file, see [texture_stacker::batch_jobs]. Options apply to every job
+/// unless the job sets them itself.
+fn run_batch(args: &[String]) -> Result<()> {
+    let args = Args::parse(args).map_err(|err| UsageError(format!("{}\n\n{}", err, USAGE)))?;
+    if args.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    if args.json {
+        enable_json_output();
+    }
+
+    let jobs = read_config(&args).and_then(|mut config| {
+        args.apply(&mut config).map_err(UsageError)?;
+        let jobs = texture_stacker::batch_jobs(&config).map_err(|err| UsageError(err.to_string()))?;
+        if jobs.is_empty() {
+            return Err(UsageError("The config file has no jobs.".to_owned()).into());
+        }
+        Ok(jobs)
+    });
+
+    let jobs = match jobs {
+        Ok(jobs) => jobs,
+        Err(err) => {
+            if args.json {
+                let mut document = serde_json::json!({
+                    "command": "batch",
+                    "success": false,
+                    "errors": [err.to_string()],
+                });
+                if let Some(config_error) = err.downcast_ref::<ConfigError>() {
+                    document["config_error"] = serde_json::json!(config_error);
+                }
+                println!("{}", serde_json::to_string_pretty(&document).unwrap());
+            }
+            return Err(err);
+        }
+    };
+
+    let start_time = Instant::now();
+    CATCHES_PANICS.store(true, Ordering::Relaxed);
+    let report = texture_stacker::run_batch(&jobs, args.parallel);
+    CATCHES_PANICS.store(false, Ordering::Relaxed);
+
+    if args.json {
+        let document = serde_json::json!({
+            "command": "batch",
+            "success": report.failed_count() == 0,
+            "seconds": start_time.elapsed().as_secs_f32(),
+            "report": report,
+        });
+        println!("{}", serde_json::to_string_pretty(&document).unwrap());
+    } else {
+        for job in &report.jobs {
+            match &job.error {
+                None => println!("Job '{}' finished in {:.2} s.", job.name, job.seconds),
+                Some(err) => {
+                    log_error!("Job '{}' failed: {}", job.name, err);
+                }
+            }
+        }
+        println!("Finished {} jobs in {} s", report.jobs.len(), start_time.elapsed().as_secs_f32());
+    }
+
+    match report.failed_count() {
+        0 => Ok(()),
+        failed => Err(format!("{} of {} jobs failed.", failed, report.jobs.len()).into()),
+    }
+}
+
+fn print_inspection(inspection: &Inspection) {
+    println!("Found {} texture sets.", inspection.sets.len());
+
+    for set in &inspection.sets {
+        println!("{} (into '{}')", set.name, set.output_texture_name);
+        for texture in &set.textures {
+            println!(
+                "  {:<16} {:>5}x{:<5} {:<14} {:>2} bit  {}",
+                texture.suffix,
+                texture.width,
+                texture.height,
+                texture.color_type,
+                texture.bit_depth,
+                texture.path.to_string_lossy());
+        }
+        if !set.missing_types.is_empty() {
+            println!("  missing: {}", set.missing_types.join(", "));
+        }
+    }
+
+    for excluded in &inspection.excluded_sets {
+        println!("Excluded {}: {}", excluded.name, excluded.reason);
+    }
+}
+
+/// Usage:
+/// - `unstack <combined texture> <output directory> <set name>=<mask file>...`
+/// - `unstack <combined texture> <output directory> --id-map <ID map> <legend file>`
+fn run_unstack(args: &[String]) -> Result<()> {
+    const USAGE: &str = "Usage: unstack <combined texture> <output directory> (<set name>=<mask file>... | --id-map <ID map> <legend file>)";
+
+    if args.len() < 3 {
+        return Err(UsageError(USAGE.to_owned()).into());
+    }
+
+    let mut set_masks = Vec::new();
+    let mut id_map = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--id-map" {
+            match (rest.next(), rest.next()) {
+                (Some(image), Some(legend)) => id_map = Some((image.clone(), legend.clone())),
+                _ => return Err(UsageError(USAGE.to_owned()).into()),
+            }
+            continue;
+        }
+
+        match arg.split_once('=') {
+            Some((name, mask_file)) => set_masks.push((name.to_owned(), mask_file.to_owned())),
+            None => return Err(UsageError(format!("Expected <set name>=<mask file>, got '{}'.", arg)).into()),
+        }
     }
 
-    config.input_directory = input_directory;
+    // The suffix of the combined texture is found like the suffixes of the inputs.
+    let combine_config = read_config(&Args::default())?;
+    let config = UnstackConfig {
+        combined_texture: args[0].clone(),
+        set_masks,
+        id_map,
+        suffix: None,
+        naming_pattern: combine_config.naming_pattern,
+        suffixes: combine_config.suffixes,
+        output_directory: Some(args[1].clone()),
+    };
 
     let start_time = Instant::now();
-    texture_stacker::run(&config, None)?;
+    texture_stacker::unstack(&config)?;
     println!("Finished in {} s", start_time.elapsed().as_secs_f32());
 
     Ok(())
 }
 
-fn get_input_directory() -> Result<String> {
-    // Use program argument if specified, otherwise prompt.
-    match env::args().nth(1) {
-        None =>
-            Ok(prompt_for_string("Input directory? ")?
-                .trim_matches('"')
-                .to_owned()),
-        Some(arg) => Ok(arg)
+/// Usage: `detect <input directory> [--write]`
+///
+/// Lists the texture types found in the input directory and suggests a suffix list, which is saved
+/// to the config file when `--write` is given.
+fn run_detect(args: &[String]) -> Result<()> {
+    const USAGE: &str = "Usage: detect <input directory> [--write]";
+
+    let (input_directory, write) = match args {
+        [input_directory] => (input_directory, false),
+        [input_directory, flag] if flag == "--write" => (input_directory, true),
+        _ => return Err(UsageError(USAGE.to_owned()).into()),
+    };
+
+    // Without config files the defaults are used, an invalid one is an error.
+    let discovered = texture_stacker::discover_config(None, Some(Path::new(input_directory)))?;
+    let mut config = Config::from(discovered.config);
+    config.input_directory = input_directory.clone();
+
+    let detection = texture_stacker::detect_texture_types(&config)?;
+    println!("Found {} texture sets.", detection.set_count);
+    for texture_type in &detection.texture_types {
+        println!(
+            "  {:<16} {:>4} sets{}",
+            texture_type.suffix,
+            texture_type.set_count,
+            if texture_type.has_alpha { "  (alpha)" } else { "" });
     }
+    println!("Suggested suffixes: {}", detection.suggested_suffixes.join(", "));
+
+    if write {
+        // Only the suffixes change, in the file that takes precedence.
+        let path = match discovered.sources.first() {
+            Some(path) => path.clone(),
+            None => texture_stacker::default_config_path()?,
+        };
+        let suffixes = detection.suggested_suffixes.into_iter().map(toml::Value::String).collect();
+        let mut settings = toml::value::Table::new();
+        settings.insert("suffixes".to_owned(), toml::Value::Array(suffixes));
+        texture_stacker::update_config_file(&path, settings)?;
+        println!("Saved the suffixes to '{}'.", path.to_string_lossy());
+    }
+
+    Ok(())
 }
 
-fn get_output_texture_name() -> Result<String> {
-    Ok(prompt_for_string("Output texture name? ")?
-        // Make sure it does not contain path separators.
-        .trim_matches(&['/', '\\'] as &[char])
-        .replace('/', "_")
-        .replace('\\', "_"))
+fn prompt_for_input_directory() -> Result<String> {
+    Ok(prompt_for_string("Input directory? ")?
+        .trim_matches('"')
+        .to_owned())
 }
 
-fn get_keep_mask_alpha() -> Result<bool> {
+fn prompt_for_output_texture_name() -> Result<String> {
+    Ok(sanitize_output_texture_name(&prompt_for_string("Output texture name? ")?))
+}
+
+fn prompt_for_keep_mask_alpha() -> Result<bool> {
     let response = prompt_for_string("Keep alpha channel? (Y/n) ")?;
     match response.trim() {
         "Y" | "y" => Ok(true),
@@ -98,7 +542,6 @@ fn get_keep_mask_alpha() -> Result<bool> {
     }
 }
 
-
 fn prompt_for_string(prompt: &str) -> Result<String> {
     print!("{}", prompt);
     io::stdout().flush()?;
@@ -108,7 +551,9 @@ fn prompt_for_string(prompt: &str) -> Result<String> {
 }
 
 fn ask_to_close_window() {
-    let _ = prompt_for_string("Press enter to close this window...");
+    if is_interactive() {
+        let _ = prompt_for_string("Press enter to close this window...");
+    }
 }
 
 fn exit_blocking(code: i32) -> ! {
@@ -124,6 +569,9 @@ fn setup_panic_handler() {
         print!("\x1b[31m[ERROR]\x1b[0m ");
         let _ = io::stdout().flush();
         default_hook(info);
-        ask_to_close_window();
+        // A batch goes on with the next job, it asks once it is done.
+        if !CATCHES_PANICS.load(Ordering::Relaxed) {
+            ask_to_close_window();
+        }
     }));
 }
@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use texture_stacker::{Progress, ProgressHandler, RunReport};
+
+/// Width of the bar in characters.
+const BAR_WIDTH: usize = 30;
+
+///
+/// Shows the progress of a run. On a terminal this is a progress bar with the current stage, file
+/// and an ETA, redrawn in place. The log messages of the library would break up the bar, so they are
+/// held back and printed by [ConsoleProgress::finish] instead.
+///
+/// When stdout is not a terminal, a plain line is printed for every file instead.
+///
+pub(crate) struct ConsoleProgress {
+    is_terminal: bool,
+}
+
+impl ConsoleProgress {
+    pub fn new() -> Self {
+        let is_terminal = io::stdout().is_terminal();
+        if is_terminal {
+            texture_stacker::set_log_printing(false);
+        }
+
+        Self { is_terminal }
+    }
+
+    pub fn handler(&self) -> ProgressHandler {
+        if self.is_terminal {
+            let start_time = Instant::now();
+            Box::new(move |progress: &Progress| draw_bar(progress, start_time))
+        } else {
+            let last_line = RefCell::new(String::new());
+            Box::new(move |progress: &Progress| {
+                let line = describe(progress);
+                if *last_line.borrow() != line {
+                    println!("[{:>3.0}%] {}", progress.fraction * 100.0, line);
+                    *last_line.borrow_mut() = line;
+                }
+            })
+        }
+    }
+
+    /// Clears the bar and prints what was held back: the warnings and errors, and the files
+    /// written according to `report`.
+    pub fn finish(&self, report: Option<&RunReport>) {
+        if !self.is_terminal {
+            return;
+        }
+
+        print!("\r\x1b[2K");
+        let _ = io::stdout().flush();
+        texture_stacker::set_log_printing(true);
+
+        let log = texture_stacker::take_log_messages();
+        for warning in &log.warnings {
+            log_warn!("{}", warning);
+        }
+        for error in &log.errors {
+            log_error!("{}", error);
+        }
+
+        for output in report.iter().flat_map(|report| &report.outputs) {
+            println!(
+                "Combined into '{}', from background to top: {}",
+                output.output_texture_name,
+                output.sets.join(", "));
+            for file in &output.files {
+                println!("{}", file.to_string_lossy());
+            }
+        }
+    }
+}
+
+/// The stage, output and file name of the progress, e.g. `Combining T_Car: Body_D.png`.
+fn describe(progress: &Progress) -> String {
+    match &progress.file {
+        Some(file) => {
+            let file_name = Path::new(file)
+                .file_name()
+                .map_or_else(|| file.clone(), |name| name.to_string_lossy().into_owned());
+            format!("{} {}: {}", progress.stage, progress.output_texture_name, file_name)
+        }
+        None => format!("{} {}", progress.stage, progress.output_texture_name),
+    }
+}
+
+fn draw_bar(progress: &Progress, start_time: Instant) {
+    let filled = (progress.fraction * BAR_WIDTH as f32).round() as usize;
+    let bar = format!("{}{}", "#".repeat(filled.min(BAR_WIDTH)), ".".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)));
+
+    // The ETA is a guess from the time taken so far, so wait for some progress before showing it.
+    let eta = if progress.fraction > 0.01 && progress.fraction < 1.0 {
+        let elapsed = start_time.elapsed().as_secs_f32();
+        let remaining = (elapsed / progress.fraction * (1.0 - progress.fraction)).round() as u32;
+        format!("  ETA {}:{:02}", remaining / 60, remaining % 60)
+    } else {
+        String::new()
+    };
+
+    print!("\r\x1b[2K[{}] {:>3.0}%{}  {}", bar, progress.fraction * 100.0, eta, describe(progress));
+    let _ = io::stdout().flush();
+}
@@ -0,0 +1,112 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use png::ColorType;
+
+use crate::filter::SetFilter;
+use crate::naming::NamingPattern;
+use crate::processing::read_image_format;
+use crate::{collect_and_group_files_by_name, get_input_directories, get_output_directory, is_texture_type, Config, Result};
+
+/// A texture type found in the input directories.
+#[derive(Debug, Clone)]
+pub struct DetectedTextureType {
+    /// The suffix from the config if the texture type matches one of its suffixes or their aliases,
+    /// otherwise the texture type as found in the file names.
+    pub suffix: String,
+    /// The number of texture sets that have a texture of this type.
+    pub set_count: usize,
+    /// Whether every texture of this type is RGBA, so its alpha channel could be used as the mask.
+    pub has_alpha: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureTypeDetection {
+    /// The number of texture sets found.
+    pub set_count: usize,
+    /// Every texture type found, in the order of `suggested_suffixes`.
+    pub texture_types: Vec<DetectedTextureType>,
+    /// The texture types ordered so they can be used as the suffix list of the config: the mask
+    /// type first, then the most common types.
+    pub suggested_suffixes: Vec<String>,
+}
+
+///
+/// Scans the input directories of the config the same way [crate::run] does and reports which
+/// texture types exist and how many sets have each of them.
+///
+/// When the set masks come from the alpha channels, the suggested mask type is the most common type
+/// where every texture has an alpha channel.
+///
+pub fn detect_texture_types(config: &Config) -> Result<TextureTypeDetection> {
+    let input_directories = get_input_directories(config)?;
+    // Without the known types, so types that are not configured yet are found as well.
+    let naming_pattern = NamingPattern::new(config.naming_pattern.as_deref(), &[], &BTreeMap::new())?;
+    let filter = SetFilter::new(config, &input_directories[0])?;
+    let files = collect_and_group_files_by_name(
+        &input_directories,
+        config.scan_depth,
+        &get_output_directory(config, &input_directories[0]),
+        &naming_pattern,
+        &filter,
+        &mut Vec::new())?;
+
+    // Suffix -> (names of the sets that have it, whether all of them have alpha).
+    let mut found = BTreeMap::<String, (BTreeSet<&str>, bool)>::new();
+
+    for (set_name, textures) in &files {
+        for file in textures {
+            let texture_type = match naming_pattern.texture_type(file) {
+                Some(texture_type) => texture_type,
+                None => continue,
+            };
+
+            let suffix = config.suffixes
+                .iter()
+                .find(|suffix| is_texture_type(texture_type, suffix, &config.suffix_aliases))
+                .map_or(texture_type, String::as_str);
+
+            let format = read_image_format(file)
+                .map_err(|err| format!("Unable to read '{}': {}", file, err))?;
+            // Only RGBA textures can be the mask source, see `combine_texture_sets`.
+            let has_alpha = format.color_type == ColorType::Rgba;
+
+            let (sets, all_have_alpha) = found
+                .entry(suffix.to_owned())
+                .or_insert_with(|| (BTreeSet::new(), true));
+            sets.insert(set_name);
+            *all_have_alpha &= has_alpha;
+        }
+    }
+
+    let mut texture_types: Vec<DetectedTextureType> = found
+        .into_iter()
+        .map(|(suffix, (sets, has_alpha))| DetectedTextureType {
+            suffix,
+            set_count: sets.len(),
+            has_alpha,
+        })
+        .collect();
+
+    // Most common first, keeping the order of the current config for types that are equally common.
+    let config_position = |suffix: &str| {
+        config.suffixes.iter().position(|s| s == suffix).unwrap_or(usize::MAX)
+    };
+    texture_types.sort_by_key(|texture_type| {
+        (Reverse(texture_type.set_count), config_position(&texture_type.suffix))
+    });
+
+    let uses_alpha_mask = config.id_map.is_none() && config.mesh_masks.is_none();
+    if uses_alpha_mask {
+        if let Some(index) = texture_types.iter().position(|texture_type| texture_type.has_alpha) {
+            let mask_type = texture_types.remove(index);
+            texture_types.insert(0, mask_type);
+        }
+    }
+
+    Ok(TextureTypeDetection {
+        set_count: files.len(),
+        suggested_suffixes: texture_types.iter().map(|texture_type| texture_type.suffix.clone()).collect(),
+        texture_types,
+    })
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{Config, Result};
+
+/// Name of the file in the input directory listing texture sets and files to leave out.
+pub(crate) const IGNORE_FILE_NAME: &str = ".texturestackerignore";
+
+/// A glob pattern along with where it was defined, to explain why something was excluded.
+struct Pattern {
+    glob: String,
+    origin: &'static str,
+}
+
+///
+/// Decides which texture sets and files are used, from the include and exclude lists of the config
+/// and the ignore file of the input directory. Patterns are globs where `*` matches any number of
+/// characters and `?` matches one, compared case-insensitively. File patterns are matched against
+/// both the file name and its path relative to the input directory, like `LOD/*`.
+///
+pub(crate) struct SetFilter {
+    include_sets: Vec<Pattern>,
+    exclude_sets: Vec<Pattern>,
+    include_files: Vec<Pattern>,
+    exclude_files: Vec<Pattern>,
+}
+
+impl SetFilter {
+    ///
+    /// Creates the filter of a config. Each line of the ignore file in `input_directory` is a pattern
+    /// excluding the texture sets and files it matches. Empty lines and lines starting with `#` are
+    /// skipped.
+    ///
+    pub fn new(config: &Config, input_directory: &Path) -> Result<Self> {
+        let patterns = |globs: &[String], origin: &'static str| -> Vec<Pattern> {
+            globs.iter().map(|glob| Pattern { glob: glob.clone(), origin }).collect()
+        };
+
+        let mut filter = Self {
+            include_sets: patterns(&config.include_sets, "include_sets"),
+            exclude_sets: patterns(&config.exclude_sets, "exclude_sets"),
+            include_files: patterns(&config.include_files, "include_files"),
+            exclude_files: patterns(&config.exclude_files, "exclude_files"),
+        };
+
+        let ignore_file = input_directory.join(IGNORE_FILE_NAME);
+        if ignore_file.is_file() {
+            let ignored: Vec<String> = fs::read_to_string(&ignore_file)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned)
+                .collect();
+
+            filter.exclude_sets.extend(patterns(&ignored, IGNORE_FILE_NAME));
+            filter.exclude_files.extend(patterns(&ignored, IGNORE_FILE_NAME));
+        }
+
+        Ok(filter)
+    }
+
+    /// Returns why a texture set is excluded, or `None` if it is used.
+    pub fn excluded_set_reason(&self, name: &str) -> Option<String> {
+        if let Some(pattern) = self.exclude_sets.iter().find(|pattern| glob_matches(&pattern.glob, name)) {
+            return Some(format!("matches '{}' in {}", pattern.glob, pattern.origin));
+        }
+
+        if !self.include_sets.is_empty() && !self.include_sets.iter().any(|pattern| glob_matches(&pattern.glob, name)) {
+            return Some("does not match include_sets".to_owned());
+        }
+
+        None
+    }
+
+    /// Whether a file is used. `relative_path` is relative to the input directory it was found in.
+    pub fn includes_file(&self, relative_path: &Path) -> bool {
+        let path = relative_path.to_string_lossy().replace('\\', "/");
+        let file_name = relative_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let matches = |pattern: &Pattern| glob_matches(&pattern.glob, &file_name) || glob_matches(&pattern.glob, &path);
+
+        let included = self.include_files.is_empty() || self.include_files.iter().any(matches);
+        included && !self.exclude_files.iter().any(matches)
+    }
+}
+
+/// Matches a glob pattern against a whole string, ignoring ASCII case.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let text: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: the pattern position after it and the text position it
+    // has matched up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character.
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::Result;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+/// A glTF document: its JSON and the contents of every buffer it references.
+pub(crate) struct GltfDocument {
+    pub json: Value,
+    pub buffers: Vec<Vec<u8>>,
+}
+
+///
+/// Loads a `.gltf` or `.glb` file along with its buffers. External buffers are resolved relative to
+/// the file, data URIs are decoded.
+///
+pub(crate) fn load_gltf(path: &Path) -> Result<GltfDocument> {
+    let bytes = fs::read(path)?;
+    let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+        read_glb(&bytes)?
+    } else {
+        (serde_json::from_slice(&bytes)?, None)
+    };
+
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut buffers = Vec::new();
+
+    for (i, buffer) in array(&json, "buffers").iter().enumerate() {
+        let data = match buffer.get("uri").and_then(Value::as_str) {
+            Some(uri) => {
+                if let Some(data) = uri.strip_prefix("data:") {
+                    let (_, encoded) = data
+                        .split_once(";base64,")
+                        .ok_or_else(|| format!("Buffer {} has an unsupported data URI.", i))?;
+                    base64::decode(encoded)?
+                } else {
+                    fs::read(directory.join(decode_uri(uri)))?
+                }
+            }
+            // A buffer without an URI refers to the binary chunk of a GLB.
+            None => bin
+                .clone()
+                .ok_or_else(|| format!("Buffer {} has no URI and there is no binary chunk.", i))?,
+        };
+
+        buffers.push(data);
+    }
+
+    Ok(GltfDocument { json, buffers })
+}
+
+/// Splits a GLB file into its JSON and optional binary chunk.
+pub(crate) fn read_glb(bytes: &[u8]) -> Result<(Value, Option<Vec<u8>>)> {
+    let read_u32 = |offset: usize| -> Result<u32> {
+        let slice = bytes.get(offset..offset + 4).ok_or("Unexpected end of GLB file.")?;
+        Ok(u32::from_le_bytes(slice.try_into().unwrap()))
+    };
+
+    if read_u32(4)? != 2 {
+        return Err("Only version 2 GLB files are supported.".into());
+    }
+
+    let length = (read_u32(8)? as usize).min(bytes.len());
+    let mut offset = 12;
+    let mut json = None;
+    let mut bin = None;
+
+    while offset + 8 <= length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or("Unexpected end of GLB file.")?;
+
+        match chunk_type {
+            GLB_CHUNK_JSON => json = Some(serde_json::from_slice(data)?),
+            GLB_CHUNK_BIN if bin.is_none() => bin = Some(data.to_vec()),
+            _ => {} // Unknown chunks are skipped.
+        }
+
+        offset += 8 + chunk_length;
+    }
+
+    Ok((json.ok_or("The GLB file has no JSON chunk.")?, bin))
+}
+
+/// Builds a GLB file from a JSON document and an optional binary chunk.
+pub(crate) fn write_glb(json: &Value, bin: Option<&[u8]>) -> Result<Vec<u8>> {
+    let mut json_chunk = serde_json::to_vec(json)?;
+    // Chunks are 4-byte aligned, the JSON chunk is padded with spaces and the binary one with zeros.
+    while !json_chunk.len().is_multiple_of(4) {
+        json_chunk.push(b' ');
+    }
+
+    let bin_chunk = bin.map(|bin| {
+        let mut chunk = bin.to_vec();
+        chunk.resize(chunk.len().div_ceil(4) * 4, 0);
+        chunk
+    });
+
+    let total_length = 12 + 8 + json_chunk.len() + bin_chunk.as_ref().map_or(0, |chunk| 8 + chunk.len());
+
+    let mut out = Vec::with_capacity(total_length);
+    out.extend_from_slice(GLB_MAGIC);
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&(total_length as u32).to_le_bytes());
+
+    out.extend_from_slice(&(json_chunk.len() as u32).to_le_bytes());
+    out.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+    out.extend_from_slice(&json_chunk);
+
+    if let Some(chunk) = bin_chunk {
+        out.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        out.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+        out.extend_from_slice(&chunk);
+    }
+
+    Ok(out)
+}
+
+impl GltfDocument {
+    ///
+    /// Reads the elements of an accessor as floats, returning the values and the number of
+    /// components per element. Normalized integer components are mapped to [0, 1] or [-1, 1].
+    ///
+    pub fn read_accessor(&self, index: usize) -> Result<(Vec<f32>, usize)> {
+        self.read_accessor_with(index, read_component)
+    }
+
+    /// Reads the elements of an index accessor.
+    pub fn read_indices(&self, index: usize) -> Result<Vec<u32>> {
+        let (indices, _) = self.read_accessor_with(index, |bytes, _, _| match bytes.len() {
+            1 => bytes[0] as u32,
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            _ => u32::from_le_bytes(bytes.try_into().unwrap()),
+        })?;
+        Ok(indices)
+    }
+
+    fn read_accessor_with<T: Clone + Default>(
+        &self,
+        index: usize,
+        read: impl Fn(&[u8], usize, bool) -> T,
+    ) -> Result<(Vec<T>, usize)> {
+        let accessor = array(&self.json, "accessors")
+            .get(index)
+            .ok_or_else(|| format!("Accessor {} does not exist.", index))?;
+
+        if accessor.get("sparse").is_some() {
+            return Err(format!("Accessor {} is sparse, which is not supported.", index).into());
+        }
+
+        let count = get_usize(accessor, "count").unwrap_or(0);
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            other => return Err(format!("Accessor {} has an unsupported type {:?}.", index, other).into()),
+        };
+        let component_type = get_usize(accessor, "componentType").unwrap_or(0);
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(format!("Accessor {} has an unsupported component type {}.", index, component_type).into()),
+        };
+        let normalized = accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false);
+
+        let view_index = match get_usize(accessor, "bufferView") {
+            Some(view_index) => view_index,
+            // Accessors without a buffer view are all zeros.
+            None => return Ok((vec![T::default(); count * components], components)),
+        };
+
+        let view = array(&self.json, "bufferViews")
+            .get(view_index)
+            .ok_or_else(|| format!("Buffer view {} does not exist.", view_index))?;
+        let buffer = get_usize(view, "buffer")
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| format!("Buffer view {} refers to a missing buffer.", view_index))?;
+
+        let element_size = components * component_size;
+        let stride = get_usize(view, "byteStride").unwrap_or(element_size);
+        let start = get_usize(view, "byteOffset").unwrap_or(0) + get_usize(accessor, "byteOffset").unwrap_or(0);
+
+        if count > 0 && start + (count - 1) * stride + element_size > buffer.len() {
+            return Err(format!("Accessor {} reads past the end of its buffer.", index).into());
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let offset = start + element * stride + component * component_size;
+                let bytes = &buffer[offset..offset + component_size];
+                values.push(read(bytes, component_type, normalized));
+            }
+        }
+
+        Ok((values, components))
+    }
+}
+
+fn read_component(bytes: &[u8], component_type: usize, normalized: bool) -> f32 {
+    match (component_type, normalized) {
+        (5120, false) => bytes[0] as i8 as f32,
+        (5120, true) => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+        (5121, false) => bytes[0] as f32,
+        (5121, true) => bytes[0] as f32 / 255.0,
+        (5122, false) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        (5122, true) => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+        (5123, false) => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        (5123, true) => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+        (5125, _) => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        _ => f32::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+/// Returns the array with the given name at the top level of a glTF object, or an empty slice.
+pub(crate) fn array<'a>(value: &'a Value, name: &str) -> &'a [Value] {
+    value.get(name).and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+pub(crate) fn get_usize(value: &Value, name: &str) -> Option<usize> {
+    value.get(name).and_then(Value::as_u64).map(|value| value as usize)
+}
+
+/// Decodes the percent-encoded characters of a relative URI.
+pub(crate) fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| uri.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
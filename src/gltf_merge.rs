@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+use serde_json::{json, Map, Value};
+
+use crate::config::GltfSlot;
+use crate::gltf::{array, decode_uri, get_usize, read_glb, write_glb};
+use crate::util::log_warn;
+use crate::Result;
+
+/// Material properties copied from the first replaced material to the merged one.
+const COPIED_MATERIAL_PROPERTIES: [&str; 3] = ["alphaMode", "alphaCutoff", "doubleSided"];
+
+///
+/// Rewrites a glTF or GLB file so that every primitive using one of the given materials uses a
+/// single new material referencing the combined textures instead. The replaced materials are kept
+/// in the file, but are no longer used.
+///
+/// URIs of external buffers and images are rewritten to stay valid relative to the output file.
+///
+/// Returns whether the output file was written, which it isn't if no material matches.
+///
+pub(crate) fn merge_gltf_materials(
+    input: &Path,
+    output: &Path,
+    material_names: &[&str],
+    merged_material_name: &str,
+    textures: &[(GltfSlot, PathBuf)],
+) -> Result<bool> {
+    let (mut json, bin, is_binary) = read_gltf(input)?;
+
+    let replaced = find_replaced_materials(&json, input, material_names);
+    if replaced.is_empty() {
+        return Ok(false);
+    }
+
+    let input_directory = absolute_directory(input)?;
+    let output_directory = absolute_directory(output)?;
+
+    // Keep external references valid from the new location.
+    for collection in ["buffers", "images"] {
+        if let Some(items) = json.get_mut(collection).and_then(Value::as_array_mut) {
+            for item in items {
+                let uri = match item.get("uri").and_then(Value::as_str) {
+                    Some(uri) if !uri.starts_with("data:") => uri.to_owned(),
+                    _ => continue,
+                };
+                let target = input_directory.join(decode_uri(&uri));
+                item["uri"] = Value::String(relative_uri(&output_directory, &target));
+            }
+        }
+    }
+
+    // Add an image and a texture for every combined texture.
+    let mut material = Map::new();
+    material.insert("name".to_owned(), json!(merged_material_name));
+    let first_replaced = &array(&json, "materials")[replaced[0]];
+    for property in COPIED_MATERIAL_PROPERTIES {
+        if let Some(value) = first_replaced.get(property) {
+            material.insert(property.to_owned(), value.clone());
+        }
+    }
+
+    let mut pbr = Map::new();
+    for (slot, texture) in textures {
+        let texture_file = texture.canonicalize()?;
+        let image_index = push(&mut json, "images", json!({ "uri": relative_uri(&output_directory, &texture_file) }));
+        let texture_index = push(&mut json, "textures", json!({ "source": image_index }));
+        let info = json!({ "index": texture_index });
+
+        match slot {
+            GltfSlot::BaseColor => {
+                pbr.insert("baseColorTexture".to_owned(), info);
+            }
+            GltfSlot::MetallicRoughness => {
+                pbr.insert("metallicRoughnessTexture".to_owned(), info);
+            }
+            GltfSlot::Normal => {
+                material.insert("normalTexture".to_owned(), info);
+            }
+            GltfSlot::Occlusion => {
+                material.insert("occlusionTexture".to_owned(), info);
+            }
+            GltfSlot::Emissive => {
+                material.insert("emissiveTexture".to_owned(), info);
+                material.insert("emissiveFactor".to_owned(), json!([1.0, 1.0, 1.0]));
+            }
+        }
+    }
+    material.insert("pbrMetallicRoughness".to_owned(), Value::Object(pbr));
+
+    let merged_index = push(&mut json, "materials", Value::Object(material));
+
+    // Switch all primitives over to the merged material.
+    if let Some(meshes) = json.get_mut("meshes").and_then(Value::as_array_mut) {
+        for mesh in meshes {
+            if let Some(primitives) = mesh.get_mut("primitives").and_then(Value::as_array_mut) {
+                for primitive in primitives {
+                    if get_usize(primitive, "material").is_some_and(|index| replaced.contains(&index)) {
+                        primitive["material"] = json!(merged_index);
+                    }
+                }
+            }
+        }
+    }
+
+    let out = if is_binary {
+        write_glb(&json, bin.as_deref())?
+    } else {
+        serde_json::to_vec_pretty(&json)?
+    };
+
+    fs::write(output, out)?;
+    Ok(true)
+}
+
+/// Checks what [merge_gltf_materials] would check, without writing anything: that `input` can be
+/// read and whether one of its materials matches. Returns whether the file would be written.
+pub(crate) fn check_gltf_materials(input: &Path, material_names: &[&str]) -> Result<bool> {
+    let (json, _, _) = read_gltf(input)?;
+    Ok(!find_replaced_materials(&json, input, material_names).is_empty())
+}
+
+/// Reads a glTF or GLB file. Returns the JSON document, the binary chunk and whether it is a GLB.
+fn read_gltf(input: &Path) -> Result<(Value, Option<Vec<u8>>, bool)> {
+    let bytes = fs::read(input)
+        .map_err(|err| format!("Unable to read the glTF file '{}': {}", input.to_string_lossy(), err))?;
+    let is_binary = bytes.starts_with(b"glTF");
+    let (json, bin) = if is_binary {
+        read_glb(&bytes)?
+    } else {
+        (serde_json::from_slice(&bytes)?, None)
+    };
+    Ok((json, bin, is_binary))
+}
+
+/// Returns the indices of the materials named like one of `material_names`, warning if there are none.
+fn find_replaced_materials(json: &Value, input: &Path, material_names: &[&str]) -> Vec<usize> {
+    let replaced: Vec<usize> = array(json, "materials")
+        .iter()
+        .enumerate()
+        .filter(|(_, material)| {
+            let name = material.get("name").and_then(Value::as_str).unwrap_or_default();
+            material_names.contains(&name)
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    if replaced.is_empty() {
+        log_warn!(
+            "None of the materials in '{}' match the names of the texture sets, it will not be rewritten.",
+            input.to_string_lossy());
+    }
+
+    replaced
+}
+
+/// Appends a value to a top level array of the document, creating it if needed, and returns its index.
+fn push(json: &mut Value, name: &str, value: Value) -> usize {
+    let items = json
+        .as_object_mut()
+        .expect("a glTF document is an object")
+        .entry(name)
+        .or_insert_with(|| json!([]));
+
+    let items = items.as_array_mut().expect("glTF collections are arrays");
+    items.push(value);
+    items.len() - 1
+}
+
+fn absolute_directory(file: &Path) -> Result<PathBuf> {
+    let directory = file.parent().filter(|parent| !parent.as_os_str().is_empty());
+    Ok(directory.unwrap_or_else(|| Path::new(".")).canonicalize()?)
+}
+
+///
+/// Builds a relative URI from a directory to a file, both absolute. If they are on different
+/// drives or roots, there is no relative path and an absolute `file://` URI is returned instead.
+///
+fn relative_uri(from_directory: &Path, to: &Path) -> String {
+    let from_directory = strip_verbatim_prefix(from_directory);
+    let to = strip_verbatim_prefix(to);
+    let from: Vec<Component> = from_directory.components().collect();
+    let to_components: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to_components).take_while(|(a, b)| a == b).count();
+
+    if common == 0 {
+        log_warn!(
+            "'{}' is not on the same drive as the glTF output, it is referenced by its absolute path.",
+            to.to_string_lossy());
+        return file_uri(&to_components);
+    }
+
+    let parts = std::iter::repeat_n("..".to_owned(), from.len() - common)
+        .chain(to_components[common..]
+            .iter()
+            .map(|component| encode_uri_component(&component.as_os_str().to_string_lossy())));
+
+    parts.collect::<Vec<_>>().join("/")
+}
+
+/// Turns `\\?\C:\...` into `C:\...` and `\\?\UNC\server\...` into `\\server\...`, the other paths are kept.
+fn strip_verbatim_prefix(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = text.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Builds an absolute `file://` URI from the components of an absolute path.
+fn file_uri(components: &[Component]) -> String {
+    let mut host = String::new();
+    let mut parts = Vec::new();
+
+    for component in components {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => parts.push(format!("{}:", drive as char)),
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    host = encode_uri_component(&server.to_string_lossy());
+                    parts.push(encode_uri_component(&share.to_string_lossy()));
+                }
+                _ => parts.push(encode_uri_component(&prefix.as_os_str().to_string_lossy())),
+            },
+            Component::RootDir => {}
+            _ => parts.push(encode_uri_component(&component.as_os_str().to_string_lossy())),
+        }
+    }
+
+    format!("file://{}/{}", host, parts.join("/"))
+}
+
+/// Percent-encodes the characters of a path component that are not allowed in a URI.
+fn encode_uri_component(component: &str) -> String {
+    component
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::processing::InputTextureSet;
+use crate::util::log_warn;
+use crate::{ExcludedSet, OutputGroup, Result};
+
+/// Texture sets that are combined into the same output textures.
+pub(crate) struct TextureSetGroup {
+    pub output_texture_name: String,
+    pub sets: Vec<InputTextureSet>,
+}
+
+///
+/// Splits the texture sets into the output groups of the config, keeping their order. Without
+/// output groups, every set goes into a single group named `default_output_texture_name`. Sets that
+/// are not in any group are added to `excluded_sets`.
+///
+pub(crate) fn group_texture_sets(
+    sets: Vec<InputTextureSet>,
+    output_groups: &[OutputGroup],
+    default_output_texture_name: &str,
+    excluded_sets: &mut Vec<ExcludedSet>,
+) -> Result<Vec<TextureSetGroup>> {
+    if output_groups.is_empty() {
+        return Ok(vec![TextureSetGroup {
+            output_texture_name: default_output_texture_name.to_owned(),
+            sets,
+        }]);
+    }
+
+    for (i, group) in output_groups.iter().enumerate() {
+        if output_groups[..i].iter().any(|other| other.output_texture_name == group.output_texture_name) {
+            return Err(format!(
+                "More than one output group is named '{}', they would overwrite each other.",
+                group.output_texture_name).into());
+        }
+    }
+
+    let patterns = output_groups
+        .iter()
+        .map(|group| {
+            group.pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|err| format!("Invalid pattern for output group '{}': {}", group.output_texture_name, err))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut groups: Vec<TextureSetGroup> = output_groups
+        .iter()
+        .map(|group| TextureSetGroup {
+            output_texture_name: group.output_texture_name.clone(),
+            sets: Vec::new(),
+        })
+        .collect();
+
+    for set in sets {
+        let index = output_groups.iter().zip(&patterns).position(|(group, pattern)| {
+            group.sets.contains(&set.name)
+                || group.prefix.as_ref().is_some_and(|prefix| set.name.starts_with(prefix.as_str()))
+                || pattern.as_ref().is_some_and(|pattern| pattern.is_match(&set.name))
+        });
+
+        match index {
+            Some(index) => groups[index].sets.push(set),
+            None => excluded_sets.push(ExcludedSet {
+                name: set.name,
+                reason: "not in any output group".to_owned(),
+            }),
+        }
+    }
+
+    Ok(groups)
+}
+
+///
+/// Sorts the texture sets into compositing order: by priority, then by their position in
+/// `set_order`, with unlisted sets after the listed ones in alphabetical order.
+///
+pub(crate) fn order_texture_sets(
+    sets: &mut [InputTextureSet],
+    set_order: &[String],
+    set_priority: &BTreeMap<String, i32>,
+) {
+    for name in set_order.iter().chain(set_priority.keys()) {
+        if !sets.iter().any(|set| &set.name == name) {
+            log_warn!("The texture set '{}' given in the set order or priorities was not found.", name);
+        }
+    }
+
+    sets.sort_by(|a, b| {
+        let key = |set: &InputTextureSet| {
+            let priority = set_priority.get(&set.name).copied().unwrap_or(0);
+            let position = set_order.iter().position(|name| name == &set.name).unwrap_or(usize::MAX);
+            (priority, position)
+        };
+
+        key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+    });
+}
@@ -1,14 +1,15 @@
 #![windows_subsystem = "windows"]
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 use eframe::{egui, Frame, NativeOptions};
-use eframe::egui::{Align, Align2, Context, Direction, Id, Layout, ProgressBar, RichText, Ui, Vec2, Window, DroppedFile};
+use eframe::egui::{Align, Align2, ComboBox, Context, Direction, Id, Layout, ProgressBar, RichText, Ui, Vec2, Window, DroppedFile};
 use nfd2::Response;
 
-use texture_stacker::{Config, ConfigFile};
+use texture_stacker::{Config, ConfigFile, PngCompression, PngFilter, Progress, ProgressHandler, RunReport, WatchOptions};
 
 fn main() {
     let mut window = MainWindow::new();
@@ -38,15 +39,43 @@ enum ProcessingStatus {
     Completed,
 }
 
+/// The settings that can be changed in the window, the only ones saved on exit.
+const EDITED_SETTINGS: [&str; 9] = [
+    "input_directory",
+    "output_texture_name",
+    "suffixes",
+    "keep_mask_alpha",
+    "generate_mipmaps",
+    "output_layered",
+    "png_compression",
+    "png_filter",
+    "optimize_png",
+];
+
 struct ProcessingState {
     progress: f32,
+    /// The current stage and file.
+    status: String,
+    /// The summary of the last build when watching.
+    last_rebuild: String,
 }
 
 struct MainWindow {
     config: Config,
     processing_status: ProcessingStatus,
-    process_thread: Option<thread::JoinHandle<Result<(), String>>>,
+    process_thread: Option<thread::JoinHandle<Result<RunReport, String>>>,
     processing_state: Arc<Mutex<ProcessingState>>,
+    run_report: Option<RunReport>,
+    /// Keep combining whenever the input textures change.
+    watch: bool,
+    stop_watching: Arc<AtomicBool>,
+    /// Cleared when the config file could not be read, so it isn't replaced by the defaults.
+    save_config_on_exit: bool,
+    /// The config file that takes precedence, where the changed settings are saved. `None` saves
+    /// them next to the executable.
+    config_path: Option<PathBuf>,
+    /// The config as it was loaded, to tell which settings were changed.
+    loaded_config: Config,
     //process_log: String,
     is_showing_error: bool,
     error_message: String,
@@ -59,7 +88,13 @@ impl MainWindow {
 
             processing_status: ProcessingStatus::None,
             process_thread: None,
-            processing_state: Arc::new(Mutex::new(ProcessingState { progress: 0.0 })),
+            processing_state: Arc::new(Mutex::new(ProcessingState { progress: 0.0, status: String::new(), last_rebuild: String::new() })),
+            run_report: None,
+            watch: false,
+            stop_watching: Arc::new(AtomicBool::new(false)),
+            save_config_on_exit: true,
+            config_path: None,
+            loaded_config: Default::default(),
             //process_log: String::new(),
 
             is_showing_error: false,
@@ -68,9 +103,18 @@ impl MainWindow {
     }
 
     pub fn init(&mut self) {
-        if let Ok(config_file) = texture_stacker::read_config_file() {
-            self.config = config_file.into();
-            println!("Loaded config file.");
+        match texture_stacker::discover_config(None, None) {
+            Ok(discovered) if discovered.sources.is_empty() => {}
+            Ok(discovered) => {
+                self.config = discovered.config.into();
+                self.loaded_config = self.config.clone();
+                self.config_path = discovered.sources.into_iter().next();
+                println!("Loaded config file.");
+            }
+            Err(err) => {
+                self.save_config_on_exit = false;
+                self.display_error(&err.to_string());
+            }
         }
     }
 
@@ -89,15 +133,58 @@ impl MainWindow {
             });
         }
 
-        if ui.button("Add Texture Type").clicked() {
-            self.config.suffixes.push("_D".to_owned());
-        }
+        ui.horizontal(|ui| {
+            if ui.button("Add Texture Type").clicked() {
+                self.config.suffixes.push("_D".to_owned());
+            }
+
+            if ui.button("Detect")
+                .on_hover_text("Replace the texture types with the ones found in the input directory.")
+                .clicked()
+            {
+                match texture_stacker::detect_texture_types(&self.config) {
+                    Ok(detection) if !detection.suggested_suffixes.is_empty() => {
+                        self.config.suffixes = detection.suggested_suffixes;
+                    }
+                    Ok(_) => self.display_error("No textures found in the input directory."),
+                    Err(err) => self.display_error(&err.to_string()),
+                }
+            }
+        });
 
         if let Some(index) = index_to_remove {
             self.config.suffixes.remove(index);
         }
     }
 
+    ///
+    /// Writes the settings shown in the window that differ from the loaded config to the config
+    /// file that takes precedence. The other settings and config files are left as they are, so
+    /// the settings of the other files still apply. Returns whether anything was written.
+    ///
+    fn save_changed_settings(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let loaded = toml::Value::try_from(ConfigFile::from(self.loaded_config.clone()))?;
+        let current = toml::Value::try_from(ConfigFile::from(self.config.clone()))?;
+
+        let mut changed = toml::value::Table::new();
+        for key in EDITED_SETTINGS {
+            if let Some(value) = current.get(key).filter(|value| loaded.get(key) != Some(*value)) {
+                changed.insert(key.to_owned(), value.clone());
+            }
+        }
+
+        if changed.is_empty() {
+            return Ok(false);
+        }
+
+        let path = match &self.config_path {
+            Some(path) => path.clone(),
+            None => texture_stacker::default_config_path()?,
+        };
+        texture_stacker::update_config_file(path, changed)?;
+        Ok(true)
+    }
+
     fn display_error(&mut self, message: &str) {
         self.error_message = message.to_owned();
         self.is_showing_error = true;
@@ -121,17 +208,45 @@ impl MainWindow {
     fn start_processing(&mut self) {
         let mut config = self.config.clone();
         let progress_mutex = self.processing_state.clone();
+        self.run_report = None;
 
         // Remove empty suffixes and duplicates.
         config.suffixes.retain(|suffix| !suffix.is_empty());
         config.suffixes = remove_duplicates(config.suffixes);
 
+        if !self.watch {
+            self.process_thread = Some(thread::spawn(move || {
+                match texture_stacker::run(&config, Some(progress_handler(progress_mutex))) {
+                    Ok(report) => Ok(report),
+                    Err(err) => Err(err.to_string()),
+                }
+            }));
+            return;
+        }
+
+        self.stop_watching.store(false, Ordering::Relaxed);
+        progress_mutex.lock().unwrap().last_rebuild.clear();
+        let stop_watching = self.stop_watching.clone();
+
         self.process_thread = Some(thread::spawn(move || {
-            match texture_stacker::run(&config, Some(Box::new(move |progress: f32| {
-                let mut state = progress_mutex.lock().unwrap();
-                state.progress = progress;
-            }))) {
-                Ok(_) => Ok(()),
+            // Shown once watching is stopped.
+            let mut last_report = RunReport::default();
+            let result = texture_stacker::watch(
+                &config,
+                &WatchOptions::default(),
+                &stop_watching,
+                Some(progress_handler(progress_mutex.clone())),
+                |rebuild| {
+                    // Already printed, taken so they don't pile up while watching.
+                    texture_stacker::take_log_messages();
+                    progress_mutex.lock().unwrap().last_rebuild = rebuild.to_string();
+                    if let Ok(report) = &rebuild.result {
+                        last_report = report.clone();
+                    }
+                });
+
+            match result {
+                Ok(()) => Ok(last_report),
                 Err(err) => Err(err.to_string()),
             }
         }));
@@ -180,6 +295,35 @@ impl MainWindow {
         ui.checkbox(&mut self.config.keep_mask_alpha, "Preserve Alpha Channel")
             .on_hover_text("Should the alpha channel be kept in the output texture?\nCheck this if your material contais transparency.");
 
+        ui.checkbox(&mut self.config.generate_mipmaps, "Generate Mipmaps")
+            .on_hover_text("Write a mip chain for each output texture as separate _mipN files.");
+
+        ui.checkbox(&mut self.config.output_layered, "Write Layered PSD")
+            .on_hover_text("Also write a Photoshop file per texture type with one masked layer per texture set.");
+
+        ui.horizontal(|ui| {
+            ui.label("PNG Compression");
+            ComboBox::from_id_source("png_compression")
+                .selected_text(format!("{:?}", self.config.png_compression))
+                .show_ui(ui, |ui| {
+                    for compression in [PngCompression::Fast, PngCompression::Default, PngCompression::Best] {
+                        ui.selectable_value(&mut self.config.png_compression, compression, format!("{:?}", compression));
+                    }
+                });
+
+            ui.label("Filter");
+            ComboBox::from_id_source("png_filter")
+                .selected_text(format!("{:?}", self.config.png_filter))
+                .show_ui(ui, |ui| {
+                    for filter in [PngFilter::Adaptive, PngFilter::None, PngFilter::Sub, PngFilter::Up, PngFilter::Avg, PngFilter::Paeth] {
+                        ui.selectable_value(&mut self.config.png_filter, filter, format!("{:?}", filter));
+                    }
+                });
+        });
+
+        ui.checkbox(&mut self.config.optimize_png, "Optimize PNG Output")
+            .on_hover_text("Try multiple filter and compression settings and reduce the color type when possible.\nThis is slow for large textures.");
+
 
         ui.label("Texture Types (filename suffixes)");
         self.draw_suffix_list(ui);
@@ -190,12 +334,16 @@ impl MainWindow {
         }
 
         ui.separator();
-        if ui.button(RichText::new("Combine").size(24.0)).clicked() {
-            if self.validate_input_fields() {
-                self.start_processing();
-                self.processing_status = ProcessingStatus::Processing;
+        ui.horizontal(|ui| {
+            if ui.button(RichText::new("Combine").size(24.0)).clicked() {
+                if self.validate_input_fields() {
+                    self.start_processing();
+                    self.processing_status = ProcessingStatus::Processing;
+                }
             }
-        }
+            ui.checkbox(&mut self.watch, "Watch for changes")
+                .on_hover_text("Keep combining whenever the input textures change, until stopped.");
+        });
     }
 
     fn draw_error_window(&mut self, ctx: &Context) {
@@ -224,7 +372,7 @@ impl MainWindow {
                 let result = handle.join().expect("Failed to join worker thread");
 
                 match result {
-                    Ok(_) => {}
+                    Ok(report) => self.run_report = Some(report),
                     Err(err) => {
                         // Hide the process dialog and show error.
                         self.processing_status = ProcessingStatus::None;
@@ -237,18 +385,47 @@ impl MainWindow {
 
     fn draw_processing_window(&mut self, ctx: &Context) {
         let is_processing = self.processing_status == ProcessingStatus::Processing;
-        let title = if is_processing { "Combining..." } else { "Completed" };
+        let title = match (is_processing, self.watch) {
+            (true, true) => "Watching for changes...",
+            (true, false) => "Combining...",
+            (false, _) => "Completed",
+        };
         Window::new(title)
             .id(Id::new("processing_window")) // required because the title changes
             .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
             .collapsible(false)
             .resizable(false)
             .show(ctx, |ui| {
-                let progress = self.processing_state.lock().unwrap().progress;
+                let (progress, status) = {
+                    let state = self.processing_state.lock().unwrap();
+                    (state.progress, state.status.clone())
+                };
 
                 ui.add(ProgressBar::new(progress)
                     .show_percentage()
                     .animate(self.processing_status == ProcessingStatus::Processing));
+                if is_processing {
+                    ui.label(status);
+                }
+
+                if is_processing && self.watch {
+                    let last_rebuild = self.processing_state.lock().unwrap().last_rebuild.clone();
+                    ui.label(last_rebuild);
+                    ui.with_layout(Layout::default().with_cross_align(Align::Center), |ui| {
+                        if ui.button("Stop").clicked() {
+                            self.stop_watching.store(true, Ordering::Relaxed);
+                        }
+                    });
+                }
+
+                if let (false, Some(report)) = (is_processing, &self.run_report) {
+                    if !report.excluded_sets.is_empty() {
+                        ui.label("Excluded texture sets:");
+                        for excluded in &report.excluded_sets {
+                            ui.label(format!("{}: {}", excluded.name, excluded.reason));
+                        }
+                    }
+                }
 
                 if !is_processing {
                     ui.with_layout(Layout::default().with_cross_align(Align::Center), |ui| {
@@ -290,9 +467,14 @@ impl eframe::App for MainWindow {
     }
 
     fn on_exit(&mut self, _gl: &eframe::glow::Context) {
+        if !self.save_config_on_exit {
+            return;
+        }
+
         // No point in handling errors here as the user will never be able to see them.
-        match texture_stacker::write_config_file(&ConfigFile::from(self.config.clone())) {
-            Ok(_) => println!("Wrote config file."),
+        match self.save_changed_settings() {
+            Ok(false) => {}
+            Ok(true) => println!("Wrote config file."),
             Err(err) => println!("Error writing config file: {}", err),
         }
     }
@@ -330,3 +512,22 @@ fn remove_duplicates<T>(input: Vec<T>) -> Vec<T>
 
     output
 }
+
+/// Returns a progress handler that shows the progress in the processing window.
+fn progress_handler(processing_state: Arc<Mutex<ProcessingState>>) -> ProgressHandler {
+    Box::new(move |progress: &Progress| {
+        let mut state = processing_state.lock().unwrap();
+        state.progress = progress.fraction;
+        state.status = match &progress.file {
+            Some(file) => format!("{} {}", progress.stage, file_name(file)),
+            None => progress.stage.to_string(),
+        };
+    })
+}
+
+/// Returns the file name of a path, or the whole path if it has none.
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or_else(|| path.to_owned(), |name| name.to_string_lossy().into_owned())
+}
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use png::{BitDepth, ColorType};
+use serde::{Deserialize, Serialize};
+
+use crate::png_encoding::{PngOptions, write_image_to_file};
+use crate::processing::{read_image_from_file, ImageFormat, RawImage};
+use crate::Result;
+
+/// One mask per texture set.
+pub(crate) type SetMasks = Vec<Vec<bool>>;
+
+/// Color of pixels not covered by any set.
+const BACKGROUND_COLOR: [u8; 3] = [0, 0, 0];
+/// Color of pixels covered by more than one set.
+const OVERLAP_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Maps the colors of an ID map to texture set names.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IdMapLegend {
+    /// Color of pixels covered by more than one set, as `#rrggbb`.
+    pub overlap: Option<String>,
+    /// Texture set name -> color, as `#rrggbb`.
+    pub sets: BTreeMap<String, String>,
+}
+
+impl IdMapLegend {
+    pub fn read_from_path(path: &str) -> Result<IdMapLegend> {
+        let raw = fs::read_to_string(path)?;
+        Ok(toml::from_str(&raw)?)
+    }
+
+    /// Returns every set with its parsed color.
+    pub(crate) fn set_colors(&self) -> Result<Vec<(String, [u8; 3])>> {
+        self.sets
+            .iter()
+            .map(|(name, color)| {
+                let color = parse_hex_color(color).map_err(|err| format!(
+                    "Invalid color for texture set '{}': {}",
+                    name,
+                    err))?;
+                Ok((name.clone(), color))
+            })
+            .collect()
+    }
+}
+
+///
+/// Writes an ID map where each set's region is colored with a distinct color, and a legend mapping
+/// the colors back to the set names. Pixels covered by more than one set are highlighted with the
+/// overlap color.
+///
+pub(crate) fn write_id_map(
+    image_file: &str,
+    legend_file: &str,
+    names: &[&str],
+    masks: &[Vec<bool>],
+    resolution: (u32, u32),
+    png_options: &PngOptions,
+) -> Result<()> {
+    let num_pixels = resolution.0 as usize * resolution.1 as usize;
+    let colors: Vec<[u8; 3]> = (0..names.len()).map(distinct_color).collect();
+
+    let mut data = Vec::with_capacity(num_pixels * 3);
+    for i in 0..num_pixels {
+        let mut covering = masks.iter().enumerate().filter(|(_, mask)| mask[i]);
+        let color = match (covering.next(), covering.next()) {
+            (None, _) => BACKGROUND_COLOR,
+            (Some((set_index, _)), None) => colors[set_index],
+            (Some(_), Some(_)) => OVERLAP_COLOR,
+        };
+        data.extend_from_slice(&color);
+    }
+
+    let image = RawImage {
+        data,
+        format: ImageFormat {
+            width: resolution.0,
+            height: resolution.1,
+            color_type: ColorType::Rgb,
+            bit_depth: BitDepth::Eight,
+        },
+    };
+    write_image_to_file(image_file, &image, png_options)?;
+
+    let legend = IdMapLegend {
+        overlap: Some(format_hex_color(OVERLAP_COLOR)),
+        sets: names
+            .iter()
+            .zip(&colors)
+            .map(|(name, &color)| (name.to_string(), format_hex_color(color)))
+            .collect(),
+    };
+    fs::write(legend_file, toml::to_string(&legend)?)?;
+
+    Ok(())
+}
+
+///
+/// Computes a mask for each of the given set colors from an ID map image. A pixel belongs to a set
+/// if every channel is within `tolerance` of the set's color.
+///
+pub(crate) fn masks_from_id_map(image: &RawImage, set_colors: &[(String, [u8; 3])], tolerance: u8) -> Result<SetMasks> {
+    let format = &image.format;
+    if format.bit_depth != BitDepth::Eight || !matches!(format.color_type, ColorType::Rgb | ColorType::Rgba) {
+        return Err(format!(
+            "ID maps must be 8-bit RGB or RGBA images, got ({:?}, {:?}).",
+            format.color_type,
+            format.bit_depth).into());
+    }
+
+    let stride = if format.color_type == ColorType::Rgba { 4 } else { 3 };
+    let matches = |pixel: &[u8], color: &[u8; 3]| {
+        pixel.iter().zip(color).all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+    };
+
+    Ok(set_colors
+        .iter()
+        .map(|(_, color)| {
+            image.data
+                .chunks_exact(stride)
+                .map(|pixel| matches(&pixel[..3], color))
+                .collect()
+        })
+        .collect())
+}
+
+/// Reads an ID map image and computes the mask of each set from it, see [masks_from_id_map].
+pub(crate) fn read_id_map_masks(file_name: &str, set_colors: &[(String, [u8; 3])], tolerance: u8) -> Result<(SetMasks, (u32, u32))> {
+    let image = read_image_from_file(file_name)?;
+    let masks = masks_from_id_map(&image, set_colors, tolerance)
+        .map_err(|err| format!("Unable to use '{}' as an ID map: {}", file_name, err))?;
+    Ok((masks, (image.format.width, image.format.height)))
+}
+
+/// Picks a color for the set with the given index, spreading hues evenly using the golden ratio.
+fn distinct_color(index: usize) -> [u8; 3] {
+    let hue = (index as f32 * 0.618_034).fract();
+    // Alternate the value a little so neighbouring hues are easier to tell apart.
+    let value = if index.is_multiple_of(2) { 0.95 } else { 0.75 };
+    hsv_to_rgb(hue, 0.8, value)
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let h = hue * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    [r, g, b].map(|channel| ((channel + m) * 255.0).round() as u8)
+}
+
+pub(crate) fn parse_hex_color(color: &str) -> Result<[u8; 3]> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a color of the form #rrggbb.", color).into());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    Ok([channel(0), channel(1), channel(2)])
+}
+
+fn format_hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::groups::group_texture_sets;
+use crate::processing::read_image_format;
+use crate::{discover_texture_sets, resolve_mask_source, Config, ExcludedSet, Result};
+
+/// The texture sets [crate::run] would combine for a config, without combining them.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Inspection {
+    /// The sets in compositing order, grouped by output.
+    pub sets: Vec<InspectedSet>,
+    pub excluded_sets: Vec<ExcludedSet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectedSet {
+    pub name: String,
+    /// The output group the set is combined into.
+    pub output_texture_name: String,
+    /// The textures the set has, in the order of the suffixes of the config.
+    pub textures: Vec<InspectedTexture>,
+    /// The suffixes of the texture types the set has no texture for.
+    pub missing_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectedTexture {
+    pub suffix: String,
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// The PNG color type, e.g. `Rgba`.
+    pub color_type: String,
+    pub bit_depth: u8,
+}
+
+/// Finds the texture sets of a config and reads the format of each of their textures.
+pub fn inspect(config: &Config) -> Result<Inspection> {
+    let mut discovered = discover_texture_sets(config)?;
+    let mut excluded_sets = discovered.excluded_sets;
+    resolve_mask_source(config, &discovered.input_directory, &mut discovered.sets)?;
+
+    let groups = group_texture_sets(
+        discovered.sets,
+        &config.output_groups,
+        &config.output_texture_name,
+        &mut excluded_sets)?;
+
+    let mut sets = Vec::new();
+
+    for group in groups {
+        for set in group.sets {
+            let mut textures = Vec::new();
+            let mut missing_types = Vec::new();
+
+            for (suffix, texture) in config.suffixes.iter().zip(&set.textures) {
+                let file = match texture {
+                    Some(file) => file,
+                    None => {
+                        missing_types.push(suffix.clone());
+                        continue;
+                    }
+                };
+
+                let format = read_image_format(file)
+                    .map_err(|err| format!("Unable to read '{}': {}", file, err))?;
+                textures.push(InspectedTexture {
+                    suffix: suffix.clone(),
+                    path: PathBuf::from(file),
+                    width: format.width,
+                    height: format.height,
+                    color_type: format!("{:?}", format.color_type),
+                    bit_depth: format.bit_depth as u8,
+                });
+            }
+
+            sets.push(InspectedSet {
+                name: set.name,
+                output_texture_name: group.output_texture_name.clone(),
+                textures,
+                missing_types,
+            });
+        }
+    }
+
+    Ok(Inspection { sets, excluded_sets })
+}
@@ -1,150 +1,599 @@
 use std::collections::BTreeMap;
 use std::error::Error;
-use std::fs;
+use std::{fs, iter};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
 
+pub use crate::batch::{batch_jobs, run_batch, BatchReport, Job, JobReport};
 pub use crate::config::*;
-use crate::processing::{combine_texture_sets, InputTextureSet, ProcessConfig};
-use crate::util::{log_warn, suffix_from_filename};
+pub use crate::config_validation::{validate_config, ConfigError, ConfigIssue};
+pub use crate::detect::{detect_texture_types, DetectedTextureType, TextureTypeDetection};
+pub use crate::id_map::IdMapLegend;
+pub use crate::inspect::{inspect, InspectedSet, InspectedTexture, Inspection};
+pub use crate::manifest::Manifest;
+pub use crate::progress::{Progress, ProgressHandler, ProgressStage};
+pub use crate::report::{ExcludedSet, OutputReport, RunReport, SetCoverage};
+pub use crate::util::{set_log_printing, take_log_messages, LogMessages};
+pub use crate::watch::{watch, Rebuild, WatchOptions};
+use crate::id_map::read_id_map_masks;
+use crate::filter::SetFilter;
+use crate::gltf_merge::{check_gltf_materials, merge_gltf_materials};
+use crate::groups::{group_texture_sets, order_texture_sets};
+use crate::mesh::load_mesh_uvs;
+use crate::png_encoding::PngOptions;
+use crate::processing::{combine_texture_sets, read_mask_from_file, split_combined_texture, InputTextureSet, MaskSource, OutputMode, ProcessConfig};
+use crate::naming::NamingPattern;
+use crate::util::{log_info, log_warn};
 
 mod processing;
 mod util;
 mod config;
+mod config_validation;
+mod mipmap;
+mod png_encoding;
+mod psd;
+mod id_map;
+mod gltf;
+mod mesh;
+mod gltf_merge;
+mod naming;
+mod detect;
+mod manifest;
+mod filter;
+mod report;
+mod groups;
+mod progress;
+mod inspect;
+mod watch;
+mod batch;
 
 pub(crate) type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 const DEFAULT_OUTPUT_DIRECTORY_NAME: &str = "Combined";
 
-pub fn run(config: &Config, progress_handler: Option<Box<dyn Fn(f32)>>) -> Result<()> {
+///
+/// Combines the texture sets found in the input directories (or the manifest) of the config into one
+/// texture per texture type.
+///
+pub fn run(config: &Config, progress_handler: Option<ProgressHandler>) -> Result<RunReport> {
+    run_with_output_mode(config, progress_handler, OutputMode::All, None)
+}
+
+/// Runs every step and check of [run] without writing any files.
+pub fn validate(config: &Config) -> Result<RunReport> {
+    run_with_output_mode(config, None, OutputMode::Nothing, None)
+}
+
+/// Like [run], but only writes the ID map of the set masks of each output, see `output_masks`.
+pub fn write_masks(config: &Config, progress_handler: Option<ProgressHandler>) -> Result<RunReport> {
+    run_with_output_mode(config, progress_handler, OutputMode::MasksOnly, None)
+}
+
+/// The texture sets found for a config.
+pub(crate) struct DiscoveredSets {
+    pub input_directory: PathBuf,
+    pub output_directory: PathBuf,
+    /// The sets in compositing order.
+    pub sets: Vec<InputTextureSet>,
+    pub excluded_sets: Vec<ExcludedSet>,
+}
+
+/// Gathers the texture sets from the manifest or the input directories of the config.
+pub(crate) fn discover_texture_sets(config: &Config) -> Result<DiscoveredSets> {
+    let input_directories = get_input_directories(config)?;
+    let input_directory = input_directories[0].clone();
+    let output_directory = get_output_directory(config, &input_directory);
+
+    let naming_pattern = NamingPattern::new(config.naming_pattern.as_deref(), &config.suffixes, &config.suffix_aliases)?;
+    let filter = SetFilter::new(config, &input_directory)?;
+    let mut excluded_sets = Vec::new();
+
+    let mut sets = match get_manifest_path(config) {
+        Some(manifest_path) => Manifest::read_from_path(&manifest_path)
+            .map_err(|err| format!("Unable to read the manifest '{}': {}", manifest_path.to_string_lossy(), err))?
+            .texture_sets(
+                manifest_path.parent().unwrap_or_else(|| Path::new("")),
+                &config.suffixes,
+                &config.suffix_aliases,
+                &filter,
+                &mut excluded_sets)?,
+        None => {
+            let files = collect_and_group_files_by_name(
+                &input_directories,
+                config.scan_depth,
+                &output_directory,
+                &naming_pattern,
+                &filter,
+                &mut excluded_sets)?;
+            gather_texture_sets(&files, &naming_pattern, &config.suffixes, &config.suffix_aliases)?
+        }
+    };
+
+    order_texture_sets(&mut sets, &config.set_order, &config.set_priority);
+
+    Ok(DiscoveredSets {
+        input_directory,
+        output_directory,
+        sets,
+        excluded_sets,
+    })
+}
+
+///
+/// Combines the texture sets of the config. With `rebuild_suffixes`, only the textures of those
+/// texture types are combined again and the masks and merged glTF are left as they are.
+///
+pub(crate) fn run_with_output_mode(
+    config: &Config,
+    progress_handler: Option<ProgressHandler>,
+    output_mode: OutputMode,
+    rebuild_suffixes: Option<&[String]>,
+) -> Result<RunReport> {
     if config.suffixes.is_empty() {
         return Err("No suffixes specified.".into());
     }
 
-    let input_directory = PathBuf::from(&config.input_directory);
-    if !input_directory.is_dir() {
-        return Err("The specified input directory is not valid.".into());
-    }
+    let DiscoveredSets {
+        input_directory,
+        output_directory,
+        sets: mut inputs,
+        excluded_sets,
+    } = discover_texture_sets(config)?;
 
-    let output_directory = match &config.output_directory {
-        None => {
-            // Output will be in a subdirectory to the input dir.
-            let mut buf = PathBuf::new();
-            buf.push(&input_directory);
-            buf.push(DEFAULT_OUTPUT_DIRECTORY_NAME);
-            buf
-        }
-        Some(path) => PathBuf::from(path),
+    let mut report = RunReport {
+        excluded_sets,
+        ..RunReport::default()
     };
 
-    if !output_directory.is_dir() {
+    if output_mode != OutputMode::Nothing && !output_directory.is_dir() {
         fs::create_dir(&output_directory)?;
     }
 
-    // Gather input sets from the input directory.
-    let mut inputs =
-        gather_texture_sets_from_directory(&input_directory, &config.suffixes)?;
+    let mask_source = resolve_mask_source(config, &input_directory, &mut inputs)?;
 
-    // Remove invalid texture sets from the list.
-    inputs.retain(|set| {
-        // Make sure the first texture type is given as this will be used for the mask.
-        let valid = set.textures.len() > 0 && set.textures[0].is_some();
-        if !valid {
-            log_warn!(
-                "Unable to compute mask for texture set '{}' because the first texture type '{}' is missing. This texture set will be skipped.",
-                set.name,
-                &config.suffixes[0]);
-        }
+    let groups = group_texture_sets(
+        inputs,
+        &config.output_groups,
+        &config.output_texture_name,
+        &mut report.excluded_sets)?;
 
-        valid
-    });
+    for excluded in &report.excluded_sets {
+        log_info!("Excluded texture set '{}': {}.", excluded.name, excluded.reason);
+    }
 
     // Process all input files.
-    let config = ProcessConfig {
+    let mut process_config = ProcessConfig {
         keep_mask_alpha: config.keep_mask_alpha,
         output_masks: config.output_masks,
+        mask_source,
+        generate_mipmaps: config.generate_mipmaps,
+        output_layered: config.output_layered,
+        png_options: PngOptions {
+            compression: config.png_compression,
+            filter: config.png_filter,
+            optimize: config.optimize_png,
+        },
         suffixes: config.suffixes.clone(),
-        output_texture_name: PathBuf::from(&config.output_texture_name),
+        output_texture_name: PathBuf::new(),
         output_directory: output_directory.clone(),
-        progress_handler,
+        output_mode,
+        rebuild_suffixes: rebuild_suffixes.map(<[String]>::to_vec),
+        progress_handler: None,
     };
 
-    combine_texture_sets(&inputs, &config)?;
+    // Every group reports its progress as its share of the whole run.
+    let progress_handler = progress_handler.map(Rc::<dyn Fn(&Progress)>::from);
+    let group_count = groups.len();
+    // Whether an earlier group wrote the merged glTF file.
+    let mut gltf_written = false;
+
+    for (group_index, group) in groups.iter().enumerate() {
+        if group.sets.is_empty() {
+            log_warn!("The output group '{}' has no texture sets and will be skipped.", group.output_texture_name);
+            continue;
+        }
+
+        process_config.output_texture_name = PathBuf::from(&group.output_texture_name);
+        process_config.progress_handler = progress_handler.clone().map(|handler| {
+            Box::new(move |progress: &Progress| {
+                handler(&Progress {
+                    fraction: (group_index as f32 + progress.fraction) / group_count as f32,
+                    ..progress.clone()
+                })
+            }) as ProgressHandler
+        });
+
+        let set_names: Vec<&str> = group.sets.iter().map(|set| set.name.as_str()).collect();
+        log_info!(
+            "Combining into '{}', from background to top: {}",
+            group.output_texture_name,
+            set_names.join(", "));
+        let start_time = Instant::now();
+        let mut combined = combine_texture_sets(&group.sets, &process_config)?;
+
+        if let (Some(gltf_merge), OutputMode::All, None) = (&config.gltf_merge, output_mode, rebuild_suffixes) {
+            let output = match &gltf_merge.output {
+                Some(output) => output_directory.join(output),
+                None => output_directory.join(Path::new(&gltf_merge.input).file_name().ok_or("The glTF input is not a file.")?),
+            };
+            // Every group after the first adds its material to the file written by the previous one.
+            let input = if gltf_written {
+                output.clone()
+            } else {
+                input_directory.join(&gltf_merge.input)
+            };
+            // A material name from the config only fits if there is one group.
+            let material_name = match &gltf_merge.material_name {
+                Some(material_name) if group_count == 1 => material_name,
+                _ => &group.output_texture_name,
+            };
+
+            let textures: Vec<(GltfSlot, PathBuf)> = combined
+                .textures
+                .iter()
+                .filter_map(|texture| {
+                    let slot = gltf_merge.slots.get(&texture.suffix)?;
+                    Some((*slot, texture.path.clone()))
+                })
+                .collect();
+
+            let written = merge_gltf_materials(
+                &input,
+                &output,
+                &set_names,
+                material_name,
+                &textures,
+            )?;
+            if written {
+                log_info!("{}", output.to_string_lossy());
+                combined.files.push(output);
+                gltf_written = true;
+            }
+        } else if let (Some(gltf_merge), OutputMode::Nothing, None) = (&config.gltf_merge, output_mode, rebuild_suffixes) {
+            // Validating only checks that the glTF input can be read and has a material to replace.
+            Path::new(&gltf_merge.input).file_name().ok_or("The glTF input is not a file.")?;
+            check_gltf_materials(&input_directory.join(&gltf_merge.input), &set_names)?;
+        }
+
+        let coverage = group.sets
+            .iter()
+            .map(|set| {
+                let (present, missing): (Vec<_>, Vec<_>) = config.suffixes
+                    .iter()
+                    .zip(&set.textures)
+                    .partition(|(_, texture)| texture.is_some());
+                SetCoverage {
+                    name: set.name.clone(),
+                    texture_types: present.into_iter().map(|(suffix, _)| suffix.clone()).collect(),
+                    missing_types: missing.into_iter().map(|(suffix, _)| suffix.clone()).collect(),
+                }
+            })
+            .collect();
+
+        report.outputs.push(OutputReport {
+            output_texture_name: group.output_texture_name.clone(),
+            sets: set_names.iter().map(|name| name.to_string()).collect(),
+            coverage,
+            textures: combined.textures.into_iter().map(|texture| texture.path).collect(),
+            files: combined.files,
+            seconds: start_time.elapsed().as_secs_f32(),
+        });
+    }
 
     // Open the destination directory when completed.
     #[cfg(windows)]
-    {
+    if config.open_output_directory && output_mode != OutputMode::Nothing {
         let _ = std::process::Command::new("explorer")
             .arg(&output_directory)
             .output();
     };
 
-    Ok(())
+    Ok(report)
 }
 
-fn collect_and_group_files_by_name<P: AsRef<Path>>(
-    directory: &P,
-) -> Result<BTreeMap<String, Vec<String>>> {
-    let directory = directory.as_ref();
-    assert!(directory.is_dir());
+/// Returns the manifest path of the config, if any. Relative paths are relative to the input directory.
+pub(crate) fn get_manifest_path(config: &Config) -> Option<PathBuf> {
+    config.manifest
+        .as_ref()
+        .map(|manifest| Path::new(&config.input_directory).join(manifest))
+}
+
+///
+/// Returns the input directory followed by the additional input directories, making sure they exist.
+/// When a manifest is used without an input directory, the manifest's directory is used instead.
+///
+pub(crate) fn get_input_directories(config: &Config) -> Result<Vec<PathBuf>> {
+    let input_directory = match get_manifest_path(config) {
+        Some(manifest) if config.input_directory.is_empty() => manifest
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        _ => PathBuf::from(&config.input_directory),
+    };
+
+    if !input_directory.is_dir() {
+        return Err("The specified input directory is not valid.".into());
+    }
+
+    let mut input_directories = vec![input_directory];
+    for directory in &config.additional_input_directories {
+        let directory = PathBuf::from(directory);
+        if !directory.is_dir() {
+            return Err(format!("The input directory '{}' is not valid.", directory.to_string_lossy()).into());
+        }
+        input_directories.push(directory);
+    }
+
+    Ok(input_directories)
+}
+
+pub(crate) fn get_output_directory(config: &Config, input_directory: &Path) -> PathBuf {
+    match &config.output_directory {
+        // Output will be in a subdirectory to the input dir.
+        None => input_directory.join(DEFAULT_OUTPUT_DIRECTORY_NAME),
+        Some(path) => PathBuf::from(path),
+    }
+}
+
+///
+/// Resolves where the set masks come from and removes the texture sets no mask can be computed
+/// for from `sets`.
+///
+pub(crate) fn resolve_mask_source(
+    config: &Config,
+    input_directory: &Path,
+    sets: &mut Vec<InputTextureSet>,
+) -> Result<MaskSource> {
+    let mask_source = match (&config.id_map, &config.mesh_masks) {
+        (None, None) => MaskSource::Alpha,
+        (Some(id_map), None) => resolve_id_map(id_map, input_directory)?,
+        (None, Some(mesh_masks)) => MaskSource::Mesh {
+            materials: load_mesh_uvs(&input_directory.join(&mesh_masks.mesh), mesh_masks.uv_set)?,
+            conservative: mesh_masks.conservative,
+        },
+        (Some(_), Some(_)) => {
+            return Err("An ID map and a mesh can not both be used as the mask source.".into());
+        }
+    };
 
+    // Remove invalid texture sets from the list.
+    sets.retain(|set| match &mask_source {
+        MaskSource::Alpha => {
+            // Make sure the first texture type is given as this will be used for the mask.
+            let valid = set.textures.len() > 0 && set.textures[0].is_some();
+            if !valid {
+                log_warn!(
+                    "Unable to compute mask for texture set '{}' because the first texture type '{}' is missing. This texture set will be skipped.",
+                    set.name,
+                    &config.suffixes[0]);
+            }
+
+            valid
+        }
+        MaskSource::IdMap { set_colors, .. } => {
+            let valid = set_colors.iter().any(|(name, _)| name == &set.name);
+            if !valid {
+                log_warn!(
+                    "Texture set '{}' has no color in the ID map. This texture set will be skipped.",
+                    set.name);
+            }
+
+            valid
+        }
+        MaskSource::Mesh { materials, .. } => {
+            let valid = materials.contains_key(&set.name);
+            if !valid {
+                log_warn!(
+                    "Texture set '{}' has no material with the same name in the mesh. This texture set will be skipped.",
+                    set.name);
+            }
+
+            valid
+        }
+    });
+
+    Ok(mask_source)
+}
+
+/// Reads the set colors of an ID map config. Relative paths are resolved against the input directory.
+fn resolve_id_map(id_map: &IdMapConfig, input_directory: &Path) -> Result<MaskSource> {
+    let mut legend = match &id_map.legend {
+        Some(path) => IdMapLegend::read_from_path(&input_directory.join(path).to_string_lossy())?,
+        None => IdMapLegend::default(),
+    };
+
+    // Colors given directly in the config take precedence over the legend file.
+    legend.sets.extend(id_map.sets.clone());
+
+    let set_colors = legend.set_colors()?;
+    if set_colors.is_empty() {
+        return Err("No texture set colors specified for the ID map.".into());
+    }
+
+    let texture = input_directory.join(&id_map.texture);
+    if !texture.is_file() {
+        return Err(format!("The ID map '{}' does not exist.", texture.to_string_lossy()).into());
+    }
+
+    Ok(MaskSource::IdMap {
+        texture: texture.to_string_lossy().into_owned(),
+        set_colors,
+        tolerance: id_map.tolerance,
+    })
+}
+
+/// Splits a combined texture back into one texture per set using the given set masks.
+pub fn unstack(config: &UnstackConfig) -> Result<()> {
+    if config.set_masks.is_empty() && config.id_map.is_none() {
+        return Err("No set masks or ID map specified.".into());
+    }
+
+    let combined_texture = Path::new(&config.combined_texture);
+    if !combined_texture.is_file() {
+        return Err(format!("The combined texture '{}' does not exist.", &config.combined_texture).into());
+    }
+
+    // The combined textures are named after the output, which may not fit the naming pattern of
+    // the inputs, so the default pattern is tried too.
+    let naming_pattern = NamingPattern::new(config.naming_pattern.as_deref(), &config.suffixes, &BTreeMap::new())?;
+    let suffix = match &config.suffix {
+        Some(suffix) => suffix.as_str(),
+        None => naming_pattern
+            .texture_type(&config.combined_texture)
+            .or_else(|| NamingPattern::default().texture_type(&config.combined_texture))
+            .ok_or_else(|| format!("Unable to determine the suffix of '{}'.", &config.combined_texture))?,
+    };
+
+    let output_directory = match &config.output_directory {
+        Some(path) => PathBuf::from(path),
+        None => combined_texture.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+
+    if !output_directory.as_os_str().is_empty() && !output_directory.is_dir() {
+        fs::create_dir_all(&output_directory)?;
+    }
+
+    let mut set_masks = Vec::new();
+    for (name, mask_file) in &config.set_masks {
+        let (mask, _) = read_mask_from_file(mask_file)?;
+        set_masks.push((name.clone(), mask));
+    }
+
+    if let Some((id_map, legend_file)) = &config.id_map {
+        // Overlapping pixels are left out, as the combined texture only holds one of the sets there.
+        let set_colors = IdMapLegend::read_from_path(legend_file)?.set_colors()?;
+        let (masks, _) = read_id_map_masks(id_map, &set_colors, 0)?;
+        for ((name, _), mask) in set_colors.into_iter().zip(masks) {
+            set_masks.push((name, mask));
+        }
+    }
+
+    split_combined_texture(
+        &config.combined_texture,
+        &set_masks,
+        suffix,
+        &output_directory,
+        &PngOptions::default(),
+    )
+}
+
+///
+/// Groups the PNG files of the given directories by texture set name. Subdirectories are scanned up
+/// to `scan_depth` levels deep, skipping `excluded_directory` so previous outputs are not picked up
+/// as inputs. Sets and files left out by the filter are not returned, the sets are added to
+/// `excluded_sets` instead.
+///
+pub(crate) fn collect_and_group_files_by_name(
+    directories: &[PathBuf],
+    scan_depth: u32,
+    excluded_directory: &Path,
+    naming_pattern: &NamingPattern,
+    filter: &SetFilter,
+    excluded_sets: &mut Vec<ExcludedSet>,
+) -> Result<BTreeMap<String, Vec<String>>> {
     // Here we store a mapping of: texture name -> list of textures with that name.
     // Using a BTreeMap instead of a HashMap here to have the items be sorted by key. This helps
     // make sure we get a consistent result when processing the textures later.
     let mut map = BTreeMap::<String, Vec<String>>::new();
+    let excluded_directory = excluded_directory.canonicalize().ok();
 
-    for entry in directory.read_dir()? {
-        let entry = entry?;
-        let path = entry.path();
+    for directory in directories {
+        let mut files = Vec::new();
+        collect_png_files(directory, scan_depth, excluded_directory.as_deref(), &mut files)?;
 
-        if path.extension() != Some("png".as_ref()) {
-            continue;
-        }
+        for path in files {
+            let included = filter.includes_file(path.strip_prefix(directory).unwrap_or(&path));
+            let path = path.to_string_lossy();
 
-        if let Some(stem) = path.file_stem() {
-            let stem = stem.to_string_lossy();
-
-            if let Some(pos) = stem.rfind('_') {
-                let pre = &stem[..pos];
-
-                match map.get_mut(pre) {
-                    Some(vec) => {
-                        vec.push(path.to_string_lossy().into_owned());
-                    }
-                    None => {
-                        let mut vec = Vec::new();
-                        vec.push(path.to_string_lossy().to_string());
-                        map.insert(pre.to_owned(), vec);
-                    }
+            if let Some((set_name, _)) = naming_pattern.parse(&path) {
+                let files = map.entry(set_name.to_owned()).or_default();
+                if included {
+                    files.push(path.into_owned());
                 }
             }
         }
     }
 
+    map.retain(|name, files| {
+        let reason = match filter.excluded_set_reason(name) {
+            Some(reason) => reason,
+            None if files.is_empty() => "all of its files are excluded".to_owned(),
+            None => return true,
+        };
+
+        excluded_sets.push(ExcludedSet { name: name.clone(), reason });
+        false
+    });
+
     Ok(map)
 }
 
-fn gather_texture_sets_from_directory<P, S>(
-    path: &P,
+pub(crate) fn collect_png_files(
+    directory: &Path,
+    depth: u32,
+    excluded_directory: Option<&Path>,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    assert!(directory.is_dir());
+
+    // Sort the entries so sets spread over several directories are always gathered in the same order.
+    let mut entries = directory
+        .read_dir()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            let excluded = excluded_directory.is_some() && path.canonicalize().ok().as_deref() == excluded_directory;
+            if depth > 0 && !excluded {
+                collect_png_files(&path, depth - 1, excluded_directory, files)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png")) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks the texture of each suffix from the grouped files of every texture set.
+fn gather_texture_sets<S>(
+    files: &BTreeMap<String, Vec<String>>,
+    naming_pattern: &NamingPattern,
     suffixes: &[S],
+    suffix_aliases: &BTreeMap<String, Vec<String>>,
 ) -> Result<Vec<InputTextureSet>>
     where
-        P: AsRef<Path>,
         S: AsRef<str>,
 {
-    let files = collect_and_group_files_by_name(path)?;
     let mut output: Vec<InputTextureSet> = Vec::new();
 
-    for (name, textures) in &files {
+    for (name, textures) in files {
         let mut texture_set = InputTextureSet {
             name: name.clone(),
             textures: vec![None; suffixes.len()],
         };
 
         for (i, suffix) in suffixes.iter().enumerate() {
-            if let Some(file) = textures
-                .iter()
-                .find(|filename| suffix_from_filename(filename) == Some(suffix.as_ref()))
-            {
+            let mut matching = textures.iter().filter(|filename| {
+                naming_pattern
+                    .texture_type(filename)
+                    .is_some_and(|texture_type| is_texture_type(texture_type, suffix.as_ref(), suffix_aliases))
+            });
+
+            if let Some(file) = matching.next() {
+                // The same set found in several places must not provide the same texture twice.
+                if let Some(other) = matching.next() {
+                    return Err(format!(
+                        "The texture set '{}' has more than one '{}' texture: '{}' and '{}'.",
+                        name,
+                        suffix.as_ref(),
+                        file,
+                        other).into());
+                }
+
                 texture_set.textures[i] = Some(file.clone());
             }
         }
@@ -154,3 +603,11 @@ fn gather_texture_sets_from_directory<P, S>(
 
     Ok(output)
 }
+
+/// Texture types are matched case-insensitively against the suffix and its aliases.
+pub(crate) fn is_texture_type(texture_type: &str, suffix: &str, suffix_aliases: &BTreeMap<String, Vec<String>>) -> bool {
+    let aliases = suffix_aliases.get(suffix).map_or(&[][..], Vec::as_slice);
+    iter::once(suffix)
+        .chain(aliases.iter().map(String::as_str))
+        .any(|alias| alias.eq_ignore_ascii_case(texture_type))
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::filter::SetFilter;
+use crate::processing::InputTextureSet;
+use crate::util::log_warn;
+use crate::{is_texture_type, ExcludedSet, Result};
+
+///
+/// Lists the texture sets to combine and the file of each of their texture types explicitly, for
+/// textures that can't be found by scanning a directory. Can be written as TOML or JSON:
+///
+/// ```toml
+/// [sets.Body]
+/// _D = "D:/Exports/Car/Body_BaseColor.png"
+/// _N = "../Shared/Body_normal.png"
+/// ```
+///
+/// Relative paths are resolved against the directory of the manifest.
+///
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    /// Texture set name -> texture type (a suffix or one of its aliases) -> file.
+    pub sets: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Manifest {
+    /// Reads a manifest, as JSON if the file has a `.json` extension and as TOML otherwise.
+    pub fn read_from_path(path: &Path) -> Result<Manifest> {
+        let raw = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+        let manifest = if is_json {
+            serde_json::from_str(&raw)?
+        } else {
+            toml::from_str(&raw)?
+        };
+
+        Ok(manifest)
+    }
+
+    /// Builds the texture sets of the manifest with their textures in the order of `suffixes`.
+    pub(crate) fn texture_sets<S: AsRef<str>>(
+        &self,
+        directory: &Path,
+        suffixes: &[S],
+        suffix_aliases: &BTreeMap<String, Vec<String>>,
+        filter: &SetFilter,
+        excluded_sets: &mut Vec<ExcludedSet>,
+    ) -> Result<Vec<InputTextureSet>> {
+        let mut output = Vec::new();
+
+        for (name, textures) in &self.sets {
+            if let Some(reason) = filter.excluded_set_reason(name) {
+                excluded_sets.push(ExcludedSet { name: name.clone(), reason });
+                continue;
+            }
+
+            let mut texture_set = InputTextureSet {
+                name: name.clone(),
+                textures: vec![None; suffixes.len()],
+            };
+            let mut has_excluded_files = false;
+
+            for (texture_type, file) in textures {
+                let index = match suffixes
+                    .iter()
+                    .position(|suffix| is_texture_type(texture_type, suffix.as_ref(), suffix_aliases))
+                {
+                    Some(index) => index,
+                    None => {
+                        log_warn!(
+                            "The texture type '{}' of texture set '{}' in the manifest is not in the suffix list and will be ignored.",
+                            texture_type,
+                            name);
+                        continue;
+                    }
+                };
+
+                if !filter.includes_file(Path::new(file)) {
+                    has_excluded_files = true;
+                    continue;
+                }
+
+                let path = directory.join(file);
+                if !path.is_file() {
+                    return Err(format!(
+                        "The '{}' texture of texture set '{}' does not exist: '{}'.",
+                        texture_type,
+                        name,
+                        path.to_string_lossy()).into());
+                }
+
+                if let Some(other) = &texture_set.textures[index] {
+                    return Err(format!(
+                        "The texture set '{}' has more than one '{}' texture: '{}' and '{}'.",
+                        name,
+                        suffixes[index].as_ref(),
+                        other,
+                        path.to_string_lossy()).into());
+                }
+
+                texture_set.textures[index] = Some(path.to_string_lossy().into_owned());
+            }
+
+            if has_excluded_files && texture_set.textures.iter().all(Option::is_none) {
+                excluded_sets.push(ExcludedSet {
+                    name: name.clone(),
+                    reason: "all of its files are excluded".to_owned(),
+                });
+                continue;
+            }
+
+            output.push(texture_set);
+        }
+
+        Ok(output)
+    }
+}
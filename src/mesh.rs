@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::gltf::{array, get_usize, load_gltf};
+use crate::Result;
+
+/// A triangle in texture space, with (0, 0) in the top left corner of the texture.
+pub(crate) type UvTriangle = [[f32; 2]; 3];
+
+const GLTF_MODE_TRIANGLES: usize = 4;
+const GLTF_MODE_TRIANGLE_STRIP: usize = 5;
+const GLTF_MODE_TRIANGLE_FAN: usize = 6;
+
+///
+/// Loads the UV triangles of an OBJ, glTF or GLB mesh, grouped by material name. For glTF files,
+/// `uv_set` selects which `TEXCOORD_n` attribute is used.
+///
+pub(crate) fn load_mesh_uvs(path: &Path, uv_set: usize) -> Result<BTreeMap<String, Vec<UvTriangle>>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("obj") => load_obj_uvs(path),
+        Some("gltf") | Some("glb") => load_gltf_uvs(path, uv_set),
+        _ => Err(format!(
+            "The mesh '{}' is not an OBJ, glTF or GLB file.",
+            path.to_string_lossy()).into()),
+    }
+}
+
+fn load_obj_uvs(path: &Path) -> Result<BTreeMap<String, Vec<UvTriangle>>> {
+    let raw = fs::read_to_string(path)?;
+    let mut texcoords: Vec<[f32; 2]> = Vec::new();
+    let mut material: Option<String> = None;
+    let mut materials = BTreeMap::<String, Vec<UvTriangle>>::new();
+
+    for (line_index, line) in raw.lines().enumerate() {
+        let error = |message: &str| format!(
+            "{}:{}: {}",
+            path.to_string_lossy(),
+            line_index + 1,
+            message);
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vt") => {
+                let mut coordinate = || -> Result<f32> {
+                    Ok(tokens.next().ok_or_else(|| error("Missing texture coordinate."))?.parse()?)
+                };
+                let (u, v) = (coordinate()?, coordinate()?);
+                // OBJ has the origin in the bottom left corner.
+                texcoords.push([u, 1.0 - v]);
+            }
+            Some("usemtl") => {
+                material = Some(tokens.collect::<Vec<_>>().join(" "));
+            }
+            Some("f") => {
+                let mut corners = Vec::new();
+                let mut has_texcoords = true;
+                for token in tokens {
+                    // Vertices are given as v, v/vt, v/vt/vn or v//vn.
+                    let texcoord = match token.split('/').nth(1) {
+                        Some(texcoord) if !texcoord.is_empty() => texcoord,
+                        _ => {
+                            has_texcoords = false;
+                            break;
+                        }
+                    };
+
+                    let index: i64 = texcoord.parse().map_err(|_| error("Invalid face index."))?;
+                    // Negative indices are relative to the end of the list.
+                    let resolved = if index < 0 { texcoords.len() as i64 + index } else { index - 1 };
+                    let uv = usize::try_from(resolved)
+                        .ok()
+                        .and_then(|index| texcoords.get(index))
+                        .ok_or_else(|| error("Face refers to a texture coordinate that does not exist."))?;
+                    corners.push(*uv);
+                }
+
+                // Faces without texture coordinates can not be placed in the texture.
+                let material = match &material {
+                    Some(material) if has_texcoords && corners.len() >= 3 => material,
+                    _ => continue,
+                };
+
+                let triangles = materials.entry(material.clone()).or_default();
+                for i in 1..corners.len() - 1 {
+                    triangles.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+fn load_gltf_uvs(path: &Path, uv_set: usize) -> Result<BTreeMap<String, Vec<UvTriangle>>> {
+    let document = load_gltf(path)?;
+    let json = &document.json;
+    let attribute = format!("TEXCOORD_{}", uv_set);
+    let mut materials = BTreeMap::<String, Vec<UvTriangle>>::new();
+
+    for mesh in array(json, "meshes") {
+        for primitive in array(mesh, "primitives") {
+            let material = get_usize(primitive, "material")
+                .and_then(|material| array(json, "materials").get(material))
+                .and_then(|material| material.get("name"))
+                .and_then(Value::as_str);
+
+            let texcoord_accessor = primitive
+                .get("attributes")
+                .and_then(|attributes| get_usize(attributes, &attribute));
+
+            let (material, texcoord_accessor) = match (material, texcoord_accessor) {
+                (Some(material), Some(accessor)) => (material, accessor),
+                _ => continue,
+            };
+
+            let (uvs, components) = document.read_accessor(texcoord_accessor)?;
+            if components != 2 {
+                return Err(format!("The {} accessor of '{}' is not two-dimensional.", attribute, path.to_string_lossy()).into());
+            }
+            let uvs: Vec<[f32; 2]> = uvs.chunks_exact(2).map(|uv| [uv[0], uv[1]]).collect();
+
+            let indices: Vec<u32> = match get_usize(primitive, "indices") {
+                Some(accessor) => document.read_indices(accessor)?,
+                None => (0..uvs.len() as u32).collect(),
+            };
+
+            let corner = |index: u32| -> Result<[f32; 2]> {
+                uvs.get(index as usize).copied().ok_or_else(|| format!(
+                    "'{}' has an index that is out of range.",
+                    path.to_string_lossy()).into())
+            };
+
+            let triangles = materials.entry(material.to_owned()).or_default();
+            match get_usize(primitive, "mode").unwrap_or(GLTF_MODE_TRIANGLES) {
+                GLTF_MODE_TRIANGLES => {
+                    for triangle in indices.chunks_exact(3) {
+                        triangles.push([corner(triangle[0])?, corner(triangle[1])?, corner(triangle[2])?]);
+                    }
+                }
+                GLTF_MODE_TRIANGLE_STRIP => {
+                    for window in indices.windows(3) {
+                        triangles.push([corner(window[0])?, corner(window[1])?, corner(window[2])?]);
+                    }
+                }
+                GLTF_MODE_TRIANGLE_FAN => {
+                    for i in 1..indices.len().saturating_sub(1) {
+                        triangles.push([corner(indices[0])?, corner(indices[i])?, corner(indices[i + 1])?]);
+                    }
+                }
+                // Points and lines don't cover any area.
+                _ => {}
+            }
+        }
+    }
+
+    Ok(materials)
+}
+
+///
+/// Rasterizes UV triangles into a mask of the given resolution. A pixel is covered if its center is
+/// inside a triangle, or in conservative mode if any part of the pixel touches a triangle, which
+/// makes sure the edges of UV islands are fully covered.
+///
+pub(crate) fn rasterize_uv_triangles(triangles: &[UvTriangle], resolution: (u32, u32), conservative: bool) -> Vec<bool> {
+    let (width, height) = (resolution.0 as usize, resolution.1 as usize);
+    let mut mask = vec![false; width * height];
+
+    for triangle in triangles {
+        let mut points = triangle.map(|[u, v]| [u * width as f32, v * height as f32]);
+
+        // Make the winding consistent so the edge functions are positive inside.
+        let area = edge_function(points[0], points[1], points[2]);
+        if area == 0.0 {
+            continue;
+        }
+        if area < 0.0 {
+            points.swap(1, 2);
+        }
+
+        // In conservative mode each edge is pushed out by half a pixel along both axes, which
+        // includes every pixel whose square overlaps the triangle.
+        let edges = [(points[0], points[1]), (points[1], points[2]), (points[2], points[0])];
+        let offsets = edges.map(|(a, b)| {
+            if conservative {
+                ((b[0] - a[0]).abs() + (b[1] - a[1]).abs()) * 0.5
+            } else {
+                0.0
+            }
+        });
+
+        let margin = if conservative { 1.0 } else { 0.0 };
+        let min_x = points.iter().map(|p| p[0]).fold(f32::MAX, f32::min) - margin;
+        let max_x = points.iter().map(|p| p[0]).fold(f32::MIN, f32::max) + margin;
+        let min_y = points.iter().map(|p| p[1]).fold(f32::MAX, f32::min) - margin;
+        let max_y = points.iter().map(|p| p[1]).fold(f32::MIN, f32::max) + margin;
+
+        // Clamp the bounding box to the texture, UVs outside of [0, 1] are not wrapped.
+        let x_range = (min_x.floor().max(0.0) as usize)..(max_x.ceil().min(width as f32).max(0.0) as usize);
+        let y_range = (min_y.floor().max(0.0) as usize)..(max_y.ceil().min(height as f32).max(0.0) as usize);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let inside = edges
+                    .iter()
+                    .zip(&offsets)
+                    .all(|((a, b), offset)| edge_function(*a, *b, center) + offset >= 0.0);
+
+                if inside {
+                    mask[y * width + x] = true;
+                }
+            }
+        }
+    }
+
+    mask
+}
+
+/// Twice the signed area of the triangle (a, b, p). Positive if p is to the left of a -> b.
+fn edge_function(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
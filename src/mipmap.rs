@@ -0,0 +1,145 @@
+use png::{BitDepth, ColorType};
+
+use crate::processing::{ImageFormat, RawImage};
+use crate::Result;
+
+///
+/// Generates the full mip chain for the given image, down to 1x1. The base level is not included
+/// in the output.
+///
+/// `owners` holds the index of the texture set that each pixel of the image came from. When
+/// downsampling, only pixels owned by the dominant set of each 2x2 block are averaged, so colors
+/// from neighbouring UV islands belonging to different sets never bleed into each other.
+///
+pub(crate) fn generate_mip_chain(image: &RawImage, owners: &[u32]) -> Result<Vec<RawImage>> {
+    let format = &image.format;
+    let num_pixels = format.width as usize * format.height as usize;
+    assert_eq!(owners.len(), num_pixels, "owner map size does not match image size");
+
+    let samples_per_pixel = match format.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => {
+            return Err("Mipmaps can not be generated for indexed color images.".into());
+        }
+    };
+
+    let bytes_per_sample = match format.bit_depth {
+        BitDepth::Eight => 1,
+        BitDepth::Sixteen => 2,
+        _ => {
+            return Err(format!(
+                "Mipmaps can not be generated for images with a bit depth of {:?}.",
+                format.bit_depth).into());
+        }
+    };
+
+    let mut levels: Vec<RawImage> = Vec::new();
+    let mut level_owners: Vec<u32> = owners.to_vec();
+
+    loop {
+        let source = levels.last().unwrap_or(image);
+        if source.format.width <= 1 && source.format.height <= 1 {
+            break;
+        }
+
+        let (next, next_owners) =
+            downsample(source, &level_owners, samples_per_pixel, bytes_per_sample);
+        levels.push(next);
+        level_owners = next_owners;
+    }
+
+    Ok(levels)
+}
+
+fn downsample(
+    image: &RawImage,
+    owners: &[u32],
+    samples_per_pixel: usize,
+    bytes_per_sample: usize,
+) -> (RawImage, Vec<u32>) {
+    let source_width = image.format.width as usize;
+    let source_height = image.format.height as usize;
+    let width = (source_width / 2).max(1);
+    let height = (source_height / 2).max(1);
+    let stride = samples_per_pixel * bytes_per_sample;
+
+    let mut data = vec![0u8; width * height * stride];
+    let mut next_owners = vec![0u32; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            // Gather the 2x2 block of source pixels, clamping at the edges for odd sizes.
+            let x0 = (x * 2).min(source_width - 1);
+            let x1 = (x * 2 + 1).min(source_width - 1);
+            let y0 = (y * 2).min(source_height - 1);
+            let y1 = (y * 2 + 1).min(source_height - 1);
+            let block = [
+                y0 * source_width + x0,
+                y0 * source_width + x1,
+                y1 * source_width + x0,
+                y1 * source_width + x1,
+            ];
+
+            // Pick the set owning most of the block. Ties go to the first sample in the block.
+            let owner = block
+                .iter()
+                .map(|&i| owners[i])
+                .max_by_key(|&candidate| {
+                    let count = block.iter().filter(|&&i| owners[i] == candidate).count();
+                    let first = block.iter().position(|&i| owners[i] == candidate).unwrap();
+                    (count, usize::MAX - first)
+                })
+                .unwrap();
+
+            let samples: Vec<usize> = block
+                .iter()
+                .copied()
+                .filter(|&i| owners[i] == owner)
+                .collect();
+
+            let dest = &mut data[(y * width + x) * stride..][..stride];
+            for channel in 0..samples_per_pixel {
+                let offset = channel * bytes_per_sample;
+                let sum: u32 = samples
+                    .iter()
+                    .map(|&i| read_sample(&image.data[i * stride + offset..], bytes_per_sample))
+                    .sum();
+                let count = samples.len() as u32;
+                // Round to nearest.
+                let average = (sum + count / 2) / count;
+                write_sample(&mut dest[offset..], bytes_per_sample, average);
+            }
+
+            next_owners[y * width + x] = owner;
+        }
+    }
+
+    let image = RawImage {
+        data,
+        format: ImageFormat {
+            width: width as u32,
+            height: height as u32,
+            ..image.format
+        },
+    };
+
+    (image, next_owners)
+}
+
+fn read_sample(slice: &[u8], bytes_per_sample: usize) -> u32 {
+    match bytes_per_sample {
+        1 => slice[0] as u32,
+        // PNG stores 16-bit samples in big-endian order.
+        _ => u16::from_be_bytes([slice[0], slice[1]]) as u32,
+    }
+}
+
+fn write_sample(slice: &mut [u8], bytes_per_sample: usize, value: u32) {
+    match bytes_per_sample {
+        1 => slice[0] = value as u8,
+        _ => slice[..2].copy_from_slice(&(value as u16).to_be_bytes()),
+    }
+}
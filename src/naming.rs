@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::Result;
+
+/// Splits on the last `_`, with the `_` being part of the texture type: `Car_Body_D` -> (`Car_Body`, `_D`).
+const DEFAULT_PATTERN: &str = r"^(?P<set>.+)(?P<type>_[^_]*)$";
+
+///
+/// Derives the texture set name and texture type (the suffix) from texture file names.
+///
+/// A pattern is either a regular expression with the named captures `set` and `type`, or a
+/// Substance Painter style template such as `$mesh_$textureSet_$channel`. In a template,
+/// `$textureSet` is the set name and `$channel` together with the literal text right before it is
+/// the texture type, so the example matches `Car_Body_BaseColor` as set `Body` with type `_BaseColor`.
+/// Any other `$variable` matches anything.
+///
+/// The channel of a template is one of the known texture types if there are any, the longest that
+/// fits, so types may contain the separator: `Car_Body_BaseColor_sRGB` is set `Body` with type
+/// `_BaseColor_sRGB`. Without known types the channel is the rest of the name after the set.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct NamingPattern {
+    regex: Regex,
+}
+
+impl Default for NamingPattern {
+    fn default() -> Self {
+        Self { regex: Regex::new(DEFAULT_PATTERN).unwrap() }
+    }
+}
+
+impl NamingPattern {
+    ///
+    /// `suffixes` and `suffix_aliases` are the known texture types used by templates, both may be
+    /// empty.
+    ///
+    pub fn new(pattern: Option<&str>, suffixes: &[String], suffix_aliases: &BTreeMap<String, Vec<String>>) -> Result<Self> {
+        let pattern = match pattern {
+            None => return Ok(Self::default()),
+            Some(pattern) if is_template(pattern) => {
+                let mut texture_types: Vec<&String> = suffixes.iter().chain(suffix_aliases.values().flatten()).collect();
+                texture_types.retain(|texture_type| !texture_type.is_empty());
+                // The alternation takes the first type that matches.
+                texture_types.sort_by_key(|texture_type| std::cmp::Reverse(texture_type.len()));
+                template_to_regex(pattern, &texture_types)?
+            }
+            Some(pattern) => pattern.to_owned(),
+        };
+
+        let regex = Regex::new(&pattern)
+            .map_err(|err| format!("Invalid naming pattern '{}': {}", pattern, err))?;
+
+        for capture in ["set", "type"] {
+            if !regex.capture_names().flatten().any(|name| name == capture) {
+                return Err(format!("The naming pattern '{}' has no '{}' capture.", pattern, capture).into());
+            }
+        }
+
+        Ok(Self { regex })
+    }
+
+    /// Returns the set name and texture type of a file, or `None` if it doesn't match the pattern.
+    pub fn parse<'a>(&self, filename: &'a str) -> Option<(&'a str, &'a str)> {
+        let stem = Path::new(filename).file_stem()?.to_str()?;
+        let captures = self.regex.captures(stem)?;
+        let set = captures.name("set")?.as_str();
+        let texture_type = captures.name("type")?.as_str();
+
+        if set.is_empty() || texture_type.is_empty() {
+            return None;
+        }
+
+        Some((set, texture_type))
+    }
+
+    /// Returns the texture type of a file, see [NamingPattern::parse].
+    pub fn texture_type<'a>(&self, filename: &'a str) -> Option<&'a str> {
+        self.parse(filename).map(|(_, texture_type)| texture_type)
+    }
+}
+
+fn is_template(pattern: &str) -> bool {
+    pattern.contains("$textureSet") || pattern.contains("$channel")
+}
+
+fn template_to_regex(template: &str, texture_types: &[&String]) -> Result<String> {
+    let mut regex = String::from("^");
+    let mut literal = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        literal.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let length = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let (variable, remainder) = rest.split_at(length);
+        rest = remainder;
+
+        match variable {
+            "textureSet" => {
+                regex.push_str(&regex::escape(&literal));
+                // As short as possible, so the channel gets the longest type.
+                regex.push_str("(?P<set>.+?)");
+            }
+            "channel" if texture_types.is_empty() => {
+                regex.push_str(&format!("(?P<type>{}.+)", regex::escape(&literal)));
+            }
+            "channel" => {
+                // The known types include the text before the channel. Types match case-insensitively.
+                let alternatives: Vec<String> = texture_types.iter().map(|texture_type| regex::escape(texture_type)).collect();
+                regex.push_str(&format!("(?P<type>(?i:{}))", alternatives.join("|")));
+            }
+            "" => return Err(format!("The naming template '{}' has a '$' without a variable name.", template).into()),
+            _ => {
+                regex.push_str(&regex::escape(&literal));
+                regex.push_str(".+?");
+            }
+        }
+        literal.clear();
+    }
+
+    regex.push_str(&regex::escape(&format!("{}{}", literal, rest)));
+    regex.push('$');
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::NamingPattern;
+
+    const TEMPLATE: &str = "$mesh_$textureSet_$channel";
+
+    #[test]
+    fn template_channel_with_underscores_from_suffixes() {
+        let suffixes = vec!["_BaseColor".to_owned(), "_BaseColor_sRGB".to_owned(), "_Normal".to_owned()];
+        let pattern = NamingPattern::new(Some(TEMPLATE), &suffixes, &BTreeMap::new()).unwrap();
+
+        assert_eq!(pattern.parse("Car_Body_BaseColor_sRGB.png"), Some(("Body", "_BaseColor_sRGB")));
+        assert_eq!(pattern.parse("Car_Body_Trim_BaseColor.png"), Some(("Body_Trim", "_BaseColor")));
+        assert_eq!(pattern.parse("Car_Body_Roughness.png"), None);
+    }
+
+    #[test]
+    fn template_channel_with_underscores_without_suffixes() {
+        let pattern = NamingPattern::new(Some(TEMPLATE), &[], &BTreeMap::new()).unwrap();
+
+        assert_eq!(pattern.parse("Car_Body_BaseColor_sRGB.png"), Some(("Body", "_BaseColor_sRGB")));
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, FilterType};
+
+use crate::config::{PngCompression, PngFilter};
+use crate::processing::{ImageFormat, RawImage};
+use crate::Result;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    pub optimize: bool,
+}
+
+/// Filter strategies tried by the optimization pass.
+const OPTIMIZE_FILTERS: [PngFilter; 6] = [
+    PngFilter::Adaptive,
+    PngFilter::None,
+    PngFilter::Sub,
+    PngFilter::Up,
+    PngFilter::Avg,
+    PngFilter::Paeth,
+];
+
+/// Compression levels tried by the optimization pass.
+const OPTIMIZE_COMPRESSIONS: [PngCompression; 2] = [PngCompression::Best, PngCompression::Default];
+
+pub(crate) fn write_image_to_file(file_name: &str, image: &RawImage, options: &PngOptions) -> Result<()> {
+    let encoded = if options.optimize {
+        encode_optimized(image)?
+    } else {
+        encode(image, options.compression, options.filter)?
+    };
+
+    fs::write(file_name, encoded)?;
+    Ok(())
+}
+
+fn encode(image: &RawImage, compression: PngCompression, filter: PngFilter) -> Result<Vec<u8>> {
+    let format = &image.format;
+    let mut buffer = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut buffer, format.width, format.height);
+    encoder.set_depth(format.bit_depth);
+    encoder.set_color(format.color_type);
+    //encoder.set_srgb(png::SrgbRenderingIntent::)
+
+    encoder.set_compression(match compression {
+        PngCompression::Fast => Compression::Fast,
+        PngCompression::Default => Compression::Default,
+        PngCompression::Best => Compression::Best,
+    });
+
+    match filter {
+        PngFilter::Adaptive => encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive),
+        PngFilter::None => encoder.set_filter(FilterType::NoFilter),
+        PngFilter::Sub => encoder.set_filter(FilterType::Sub),
+        PngFilter::Up => encoder.set_filter(FilterType::Up),
+        PngFilter::Avg => encoder.set_filter(FilterType::Avg),
+        PngFilter::Paeth => encoder.set_filter(FilterType::Paeth),
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.data)?;
+    writer.finish()?;
+
+    Ok(buffer)
+}
+
+///
+/// Reduces the image losslessly and then encodes it with every combination of filter strategy and
+/// compression level, keeping the smallest result.
+///
+fn encode_optimized(image: &RawImage) -> Result<Vec<u8>> {
+    let reduced = reduce_losslessly(image);
+    let image = reduced.as_ref().unwrap_or(image);
+
+    let mut smallest: Option<Vec<u8>> = None;
+    for compression in OPTIMIZE_COMPRESSIONS {
+        for filter in OPTIMIZE_FILTERS {
+            let encoded = encode(image, compression, filter)?;
+            let is_smaller = match &smallest {
+                Some(smallest) => encoded.len() < smallest.len(),
+                None => true,
+            };
+
+            if is_smaller {
+                smallest = Some(encoded);
+            }
+        }
+    }
+
+    Ok(smallest.unwrap())
+}
+
+///
+/// Converts the image to a smaller color type and/or bit depth if that can be done without losing
+/// any information. Returns `None` if no reduction is possible.
+///
+/// - 16-bit images where every sample is representable in 8 bits are reduced to 8 bits.
+/// - Alpha channels where every pixel is fully opaque are dropped.
+/// - Color images where every pixel is gray are reduced to grayscale.
+///
+fn reduce_losslessly(image: &RawImage) -> Option<RawImage> {
+    let format = &image.format;
+    let channels = match format.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => return None,
+    };
+    let bytes_per_sample = match format.bit_depth {
+        BitDepth::Eight => 1,
+        BitDepth::Sixteen => 2,
+        _ => return None,
+    };
+
+    let has_alpha = matches!(format.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba);
+    let is_color = matches!(format.color_type, ColorType::Rgb | ColorType::Rgba);
+    let stride = channels * bytes_per_sample;
+    let pixels = || image.data.chunks_exact(stride);
+
+    // A 16-bit sample fits in 8 bits if both of its bytes are equal (i.e. the value is v * 257).
+    let reduce_depth = bytes_per_sample == 2
+        && image.data.chunks_exact(2).all(|sample| sample[0] == sample[1]);
+
+    let alpha_offset = (channels - 1) * bytes_per_sample;
+    let drop_alpha = has_alpha
+        && pixels().all(|pixel| pixel[alpha_offset..].iter().all(|&byte| byte == 0xff));
+
+    let to_gray = is_color
+        && pixels().all(|pixel| {
+            let (r, rest) = pixel.split_at(bytes_per_sample);
+            let (g, b) = rest.split_at(bytes_per_sample);
+            r == g && g == &b[..bytes_per_sample]
+        });
+
+    if !reduce_depth && !drop_alpha && !to_gray {
+        return None;
+    }
+
+    let keep_alpha = has_alpha && !drop_alpha;
+    let color_type = match (is_color && !to_gray, keep_alpha) {
+        (true, true) => ColorType::Rgba,
+        (true, false) => ColorType::Rgb,
+        (false, true) => ColorType::GrayscaleAlpha,
+        (false, false) => ColorType::Grayscale,
+    };
+    let out_bytes_per_sample = if reduce_depth { 1 } else { bytes_per_sample };
+
+    // Indices of the input channels to keep.
+    let mut kept_channels: Vec<usize> = if is_color && !to_gray { vec![0, 1, 2] } else { vec![0] };
+    if keep_alpha {
+        kept_channels.push(channels - 1);
+    }
+
+    let mut data = Vec::with_capacity(pixels().len() * kept_channels.len() * out_bytes_per_sample);
+    for pixel in pixels() {
+        for &channel in &kept_channels {
+            let sample = &pixel[channel * bytes_per_sample..][..bytes_per_sample];
+            data.extend_from_slice(&sample[..out_bytes_per_sample]);
+        }
+    }
+
+    Some(RawImage {
+        data,
+        format: ImageFormat {
+            color_type,
+            bit_depth: if reduce_depth { BitDepth::Eight } else { format.bit_depth },
+            ..*format
+        },
+    })
+}
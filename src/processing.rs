@@ -1,7 +1,13 @@
-use std::{fs::File, io::BufWriter, path::PathBuf};
+use std::{collections::BTreeMap, fs::File, path::{Path, PathBuf}};
 
 use png::{BitDepth, ColorType};
 
+use crate::id_map::{read_id_map_masks, write_id_map};
+use crate::mesh::{rasterize_uv_triangles, UvTriangle};
+use crate::mipmap::generate_mip_chain;
+use crate::png_encoding::{PngOptions, write_image_to_file};
+use crate::psd::{PsdLayer, write_psd};
+use crate::progress::{ProgressHandler, ProgressStage, ProgressTracker};
 use crate::Result;
 use crate::util::{log_error, log_info, log_warn};
 
@@ -11,32 +17,67 @@ pub(crate) struct InputTextureSet {
     pub textures: Vec<Option<String>>,
 }
 
+/// Where the mask of each texture set comes from.
+pub(crate) enum MaskSource {
+    /// The alpha channel of the first texture type of each set.
+    Alpha,
+    /// A material ID map, with a color per texture set name.
+    IdMap {
+        texture: String,
+        set_colors: Vec<(String, [u8; 3])>,
+        tolerance: u8,
+    },
+    /// UV triangles of a mesh, grouped by material name.
+    Mesh {
+        materials: BTreeMap<String, Vec<UvTriangle>>,
+        conservative: bool,
+    },
+}
+
+/// Which files [combine_texture_sets] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputMode {
+    /// The combined textures and every extra output that is enabled.
+    All,
+    /// Only the ID map of the set masks.
+    MasksOnly,
+    /// No files at all, everything is still computed and checked.
+    Nothing,
+}
+
 pub(crate) struct ProcessConfig {
     pub keep_mask_alpha: bool,
     pub suffixes: Vec<String>,
     pub output_masks: bool,
+    pub mask_source: MaskSource,
+    pub generate_mipmaps: bool,
+    pub output_layered: bool,
+    pub png_options: PngOptions,
     pub output_directory: PathBuf,
     pub output_texture_name: PathBuf,
-    pub progress_handler: Option<Box<dyn Fn(f32)>>,
+    pub output_mode: OutputMode,
+    /// Only combine these texture types, without writing the ID map.
+    pub rebuild_suffixes: Option<Vec<String>>,
+    pub progress_handler: Option<ProgressHandler>,
 }
 
 pub(crate) struct RawImage {
-    data: Vec<u8>,
-    format: ImageFormat,
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-struct ImageFormat {
-    width: u32,
-    height: u32,
-    color_type: ColorType,
-    bit_depth: BitDepth,
+pub(crate) struct ImageFormat {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: ColorType,
+    pub bit_depth: BitDepth,
 }
 
 #[derive(PartialEq, Eq)]
 struct Pixel(u8, u8, u8, u8);
 
-fn read_image_from_file(file_name: &str) -> Result<RawImage> {
+pub(crate) fn read_image_from_file(file_name: &str) -> Result<RawImage> {
     let infile = File::open(&file_name)?;
     let decoder = png::Decoder::new(infile);
 
@@ -63,30 +104,25 @@ fn read_image_from_file(file_name: &str) -> Result<RawImage> {
     })
 }
 
-fn write_image_to_file(file_name: &str, image: &RawImage) -> Result<()> {
-    let format = &image.format;
-
-    let file = File::create(&file_name)?;
-    let mut w = BufWriter::new(file);
-
-    let mut encoder = png::Encoder::new(&mut w, format.width, format.height);
-    encoder.set_depth(format.bit_depth);
-    encoder.set_color(format.color_type);
-    //encoder.set_srgb(png::SrgbRenderingIntent::)
-
-    let mut writer = encoder.write_header()?;
-    writer.write_image_data(&image.data)?;
-
-    Ok(())
+/// Reads only the header of an image.
+pub(crate) fn read_image_format(file_name: &str) -> Result<ImageFormat> {
+    let infile = File::open(file_name)?;
+    let reader = png::Decoder::new(infile).read_info()?;
+    let info = reader.info();
+
+    Ok(ImageFormat {
+        width: info.width,
+        height: info.height,
+        bit_depth: info.bit_depth,
+        color_type: info.color_type,
+    })
 }
 
 ///
 /// Calculate the amount of bytes per pixel of the given image format.
 ///
-/// Panics if the format is not supported.
-///
-fn calc_pixel_stride(format: &ImageFormat) -> usize {
-    match format {
+fn calc_pixel_stride(format: &ImageFormat) -> Result<usize> {
+    Ok(match format {
         ImageFormat {
             bit_depth: BitDepth::Eight,
             color_type: ColorType::Rgb,
@@ -107,17 +143,23 @@ fn calc_pixel_stride(format: &ImageFormat) -> usize {
             color_type: ColorType::Rgba,
             ..
         } => 8,
-        _ => panic!("Format not supported"),
-    }
+        _ => return Err(unsupported_format(format)),
+    })
+}
+
+/// The error for an image format the pixel functions can not handle.
+fn unsupported_format(format: &ImageFormat) -> Box<dyn std::error::Error> {
+    format!(
+        "The image format ({:?}, {:?}) is not supported, only 8-bit RGB and RGBA images can be combined.",
+        format.color_type,
+        format.bit_depth).into()
 }
 
 ///
 /// Interprets a slice of bytes as a pixel in the given image format.
 ///
-/// Panics if the format is not supported.
-///
-fn bytes_to_pixel(slice: &[u8], format: &ImageFormat) -> Pixel {
-    match format {
+fn bytes_to_pixel(slice: &[u8], format: &ImageFormat) -> Result<Pixel> {
+    Ok(match format {
         ImageFormat {
             bit_depth: BitDepth::Eight,
             color_type: ColorType::Rgb,
@@ -128,16 +170,14 @@ fn bytes_to_pixel(slice: &[u8], format: &ImageFormat) -> Pixel {
             color_type: ColorType::Rgba,
             ..
         } => Pixel(slice[0], slice[1], slice[2], slice[3]),
-        _ => panic!("Format not supported"),
-    }
+        _ => return Err(unsupported_format(format)),
+    })
 }
 
 ///
 /// Writes the byte representation of pixel in the given format to the given slice.
 ///
-/// Panics if the format is not supported.
-///
-fn pixel_to_bytes(pixel: Pixel, format: &ImageFormat, slice: &mut [u8]) {
+fn pixel_to_bytes(pixel: Pixel, format: &ImageFormat, slice: &mut [u8]) -> Result<()> {
     match format {
         ImageFormat {
             bit_depth: BitDepth::Eight,
@@ -158,20 +198,20 @@ fn pixel_to_bytes(pixel: Pixel, format: &ImageFormat, slice: &mut [u8]) {
             slice[2] = pixel.2;
             slice[3] = pixel.3;
         }
-        _ => panic!("Format not supported"),
+        _ => return Err(unsupported_format(format)),
     }
+
+    Ok(())
 }
 
-fn create_mask_from_alpha_channel(image: &RawImage) -> Vec<bool> {
+fn create_mask_from_alpha_channel(image: &RawImage) -> Result<Vec<bool>> {
     let format = &image.format;
 
-    assert_eq!(
-        format.color_type,
-        ColorType::Rgba,
-        "mask texture is missing alpha channel"
-    );
+    if format.color_type != ColorType::Rgba {
+        return Err("The mask texture has no alpha channel.".into());
+    }
 
-    let pixel_stride = calc_pixel_stride(format);
+    let pixel_stride = calc_pixel_stride(format)?;
     let num_pixels = format.width as usize * format.height as usize;
 
     assert_eq!(image.data.len(), num_pixels * pixel_stride);
@@ -179,110 +219,180 @@ fn create_mask_from_alpha_channel(image: &RawImage) -> Vec<bool> {
     let mut pixel_mask = vec![false; num_pixels];
 
     for i in 0..num_pixels {
-        let pixel = bytes_to_pixel(&image.data[i * pixel_stride..], format);
+        let pixel = bytes_to_pixel(&image.data[i * pixel_stride..], format)?;
         pixel_mask[i] = pixel.3 != 0;
     }
 
-    pixel_mask
+    Ok(pixel_mask)
+}
+
+/// A combined texture written by [combine_texture_sets].
+pub(crate) struct CombinedTexture {
+    pub suffix: String,
+    pub path: PathBuf,
 }
 
-pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &ProcessConfig) -> Result<()> {
+/// What [combine_texture_sets] produced.
+#[derive(Default)]
+pub(crate) struct CombinedOutput {
+    /// The combined texture of each texture type, also when it was not written.
+    pub textures: Vec<CombinedTexture>,
+    /// Every file written, including mipmaps, layered files and the ID map.
+    pub files: Vec<PathBuf>,
+}
+
+pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &ProcessConfig) -> Result<CombinedOutput> {
     // Assumptions.
     for texture_set in input_sets {
         assert!(texture_set.textures.len() > 0);
-        assert!(texture_set.textures[0].is_some());
+        if let MaskSource::Alpha = config.mask_source {
+            assert!(texture_set.textures[0].is_some());
+        }
     }
 
-    let max_progress = (input_sets.len() * config.suffixes.len() + input_sets.len() + 1) as f32;
-    let mut progress = 0f32;
-    let mut increment_progress = || {
-        progress += 1.0;
-        if let Some(progress_handler) = &config.progress_handler {
-            progress_handler((progress / max_progress).clamp(0.0, 1.0));
-        }
-    };
+    let mut progress = ProgressTracker::new(
+        config.progress_handler.as_deref(),
+        &config.output_texture_name.to_string_lossy(),
+        input_sets.len() * config.suffixes.len() + input_sets.len() + 1);
 
     // Pixel mask for each texture set.
     let mut set_masks = vec![];
     let mut working_res = (0u32, 0u32);
 
-    // Compute masks for each texture set.
-    for input_set in input_sets {
-        let file_name = input_set.textures[0].as_ref().expect("the first texture of the set was not present");
-        let image = read_image_from_file(&file_name)?;
-        let image_format = &image.format;
-        let image_size = (image_format.width, image_format.height);
+    match &config.mask_source {
+        MaskSource::Alpha => {
+            // Compute masks for each texture set.
+            for input_set in input_sets {
+                let file_name = input_set.textures[0].as_ref().expect("the first texture of the set was not present");
+                progress.begin(ProgressStage::Masks, Some(file_name.as_str()));
+                let image = read_image_from_file(&file_name)?;
+                let image_format = &image.format;
+                let image_size = (image_format.width, image_format.height);
+
+                // Need alpha channel for mask
+                if image_format.color_type != ColorType::Rgba {
+                    return Err(format!(
+                        "The image '{}' needs to have an alpha channel in order for a mask to be computed.",
+                        &file_name).into());
+                }
 
-        // Need alpha channel for mask
-        if image_format.color_type != ColorType::Rgba {
-            return Err(format!(
-                "The image '{}' needs to have an alpha channel in order for a mask to be computed.",
-                &file_name).into());
-        }
+                if image_size == (0, 0) {
+                    return Err(format!("The image '{}' is zero sized.", &file_name).into());
+                }
+
+                if working_res == (0, 0) {
+                    working_res = image_size;
+                } else {
+                    if image_size != working_res {
+                        return Err(format!(
+                            "The image '{}' does not have the same resolution {:?} as the previous image(s) {:?}.",
+                            &file_name,
+                            image_size,
+                            working_res).into());
+                    }
+                }
+
+                let mask = create_mask_from_alpha_channel(&image)
+                    .map_err(|err| format!("Unable to use '{}' as a mask: {}", &file_name, err))?;
+                set_masks.push(mask);
 
-        if image_size == (0, 0) {
-            return Err(format!("The image '{}' is zero sized.", &file_name).into());
+                progress.step();
+            }
         }
+        MaskSource::IdMap { texture, set_colors, tolerance } => {
+            // Derive the mask of every texture set from the ID map.
+            let colors: Vec<(String, [u8; 3])> = input_sets
+                .iter()
+                .map(|input_set| {
+                    set_colors
+                        .iter()
+                        .find(|(name, _)| name == &input_set.name)
+                        .cloned()
+                        .expect("the texture set has no color in the ID map")
+                })
+                .collect();
+
+            progress.begin(ProgressStage::Masks, Some(texture.as_str()));
+            let (masks, resolution) = read_id_map_masks(texture, &colors, *tolerance)?;
+            if resolution == (0, 0) {
+                return Err(format!("The image '{}' is zero sized.", texture).into());
+            }
 
-        if working_res == (0, 0) {
-            working_res = image_size;
-        } else {
-            if image_size != working_res {
-                return Err(format!(
-                    "The image '{}' does not have the same resolution {:?} as the previous image(s) {:?}.",
-                    &file_name,
-                    image_size,
-                    working_res).into());
+            set_masks = masks;
+            working_res = resolution;
+
+            for _ in input_sets {
+                progress.step();
             }
         }
+        MaskSource::Mesh { materials, conservative } => {
+            // The masks are rasterized at the resolution of the textures.
+            let first_texture = input_sets
+                .iter()
+                .flat_map(|input_set| input_set.textures.iter().flatten())
+                .next()
+                .ok_or("None of the texture sets have any textures.")?;
+            let format = read_image_format(first_texture)?;
+            working_res = (format.width, format.height);
+
+            if working_res == (0, 0) {
+                return Err(format!("The image '{}' is zero sized.", first_texture).into());
+            }
 
-        let mask = create_mask_from_alpha_channel(&image);
-        set_masks.push(mask);
+            progress.begin(ProgressStage::Masks, None);
+            for input_set in input_sets {
+                let triangles = materials
+                    .get(&input_set.name)
+                    .expect("the texture set has no material in the mesh");
+                set_masks.push(rasterize_uv_triangles(triangles, working_res, *conservative));
 
-        increment_progress();
+                progress.step();
+            }
+        }
     }
 
-    if config.output_masks {
-        // Write masks to files for debugging.
-        for (i, mask) in set_masks.iter().enumerate() {
-            let num_pixels = mask.len();
-            let format = ImageFormat {
-                width: working_res.0,
-                height: working_res.1,
-                bit_depth: BitDepth::Eight,
-                color_type: ColorType::Rgba,
-            };
-            let stride = calc_pixel_stride(&format);
-            let mut buffer = vec![0u8; num_pixels * stride];
-
-            for i in 0..num_pixels {
-                let pixel = if mask[i] {
-                    Pixel(255, 255, 255, 255)
-                } else {
-                    Pixel(0, 0, 0, 255)
-                };
-                pixel_to_bytes(pixel, &format, &mut buffer[i * stride..]);
-            }
+    let write_outputs = config.output_mode == OutputMode::All;
+    let write_masks = ((config.output_masks && write_outputs) || config.output_mode == OutputMode::MasksOnly)
+        && config.rebuild_suffixes.is_none();
 
-            let filename = format!("{}/mask{}.png", config.output_directory.to_string_lossy(), i);
-            write_image_to_file(
-                &filename,
-                &RawImage {
-                    data: buffer,
-                    format,
-                },
-            )
-            .unwrap_or_else(|err| {
-                log_error!("Failed to write mask to file '{}': {:?}", filename, err);
-            });
+    let mut output = CombinedOutput::default();
+
+    if write_masks {
+        // Write an ID map of the set masks for debugging.
+        let output_name = config.output_texture_name.to_string_lossy();
+        let image_file = format!("{}/{}_ID.png", config.output_directory.to_string_lossy(), output_name);
+        let legend_file = format!("{}/{}_ID.toml", config.output_directory.to_string_lossy(), output_name);
+        let names: Vec<&str> = input_sets.iter().map(|set| set.name.as_str()).collect();
+
+        progress.begin(ProgressStage::Writing, Some(image_file.as_str()));
+        log_info!("{}", image_file);
+        match write_id_map(&image_file, &legend_file, &names, &set_masks, working_res, &config.png_options) {
+            Ok(()) => output.files.extend([PathBuf::from(image_file), PathBuf::from(legend_file)]),
+            Err(err) => log_error!("Failed to write ID map to file '{}': {:?}", image_file, err),
         }
     }
 
+    if config.output_mode == OutputMode::MasksOnly {
+        progress.finish();
+        return Ok(output);
+    }
+
     // Combine all the image sets into the output files.
     for (suffix_index, suffix) in config.suffixes.iter().enumerate() {
+        if config.rebuild_suffixes.as_ref().is_some_and(|suffixes| !suffixes.contains(suffix)) {
+            continue;
+        }
+
         let mut output_image: Option<RawImage> = None;
         let mut first = true;
 
+        // Index of the texture set each output pixel was taken from. Used to keep sets apart when
+        // downsampling.
+        let mut owners = vec![0u32; working_res.0 as usize * working_res.1 as usize];
+
+        // Images of the sets that contributed to the output, kept for the layered output.
+        let mut layer_images: Vec<(usize, RawImage)> = Vec::new();
+
         for (set_index, input_set) in input_sets.iter().enumerate() {
             // Grab the texture filename if it exists.
             let texture_filename;
@@ -292,25 +402,27 @@ pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &Proc
                 continue;
             }
 
+            progress.begin(ProgressStage::Combining, Some(texture_filename.as_str()));
             let image = read_image_from_file(texture_filename)?;
             let format = &image.format;
-            let is_mask_source_image = suffix_index == 0;
+            // With an ID map or mesh the first texture type is an ordinary texture and keeps its alpha.
+            let is_mask_source_image = suffix_index == 0 && matches!(config.mask_source, MaskSource::Alpha);
+
+            // Every image must match the resolution of the masks.
+            let input_size = (format.width, format.height);
+            if input_size != working_res {
+                return Err(format!(
+                    "The image '{}' does not have the same resolution {:?} as the mask(s) {:?}.",
+                    &texture_filename,
+                    input_size,
+                    working_res).into());
+            }
 
             if let Some(raw_output_image) = &output_image {
                 // Output image has already been created. Validate the current image's format
                 // against the output image's one.
 
                 let output_format = &raw_output_image.format;
-                let output_size = (output_format.width, output_format.height);
-                let input_size = (format.width, format.height);
-
-                if input_size != output_size {
-                    return Err(format!(
-                        "The image '{}' does not have the same resolution {:?} as the previous image(s) {:?}.",
-                        &texture_filename,
-                        input_size,
-                        output_size).into());
-                }
 
                 if format.bit_depth != output_format.bit_depth {
                     return Err(format!(
@@ -349,7 +461,7 @@ pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &Proc
 
                 let buffer_size = format.width as usize
                     * format.height as usize
-                    * calc_pixel_stride(&output_format);
+                    * calc_pixel_stride(&output_format)?;
 
                 output_image = Some(RawImage {
                     data: vec![0; buffer_size],
@@ -359,14 +471,26 @@ pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &Proc
 
             if first {
                 // For the first image in the set we just copy the image without masking to get a nice background color for the output image.
-                copy_image(&image, output_image.as_mut().unwrap());
+                copy_image(&image, output_image.as_mut().unwrap())
+                    .map_err(|err| format!("Unable to combine '{}': {}", texture_filename, err))?;
+                owners.fill(set_index as u32);
                 first = false;
             } else {
                 let mask = &set_masks[set_index];
-                copy_image_masked(&image, output_image.as_mut().unwrap(), &mask);
+                copy_image_masked(&image, output_image.as_mut().unwrap(), &mask)
+                    .map_err(|err| format!("Unable to combine '{}': {}", texture_filename, err))?;
+                for (owner, &masked) in owners.iter_mut().zip(mask) {
+                    if masked {
+                        *owner = set_index as u32;
+                    }
+                }
+            }
+
+            if config.output_layered && write_outputs {
+                layer_images.push((set_index, image));
             }
 
-            increment_progress();
+            progress.step();
         }
 
         if let Some(image) = &output_image {
@@ -377,17 +501,163 @@ pub(crate) fn combine_texture_sets(input_sets: &[InputTextureSet], config: &Proc
             output_file_path.set_extension("png");
 
             let output_file = output_file_path.to_str().unwrap();
-            log_info!("{}", output_file);
-            write_image_to_file(output_file, &image)?;
+            if write_outputs {
+                progress.begin(ProgressStage::Writing, Some(output_file));
+                log_info!("{}", output_file);
+                write_image_to_file(output_file, &image, &config.png_options)?;
+                output.files.push(output_file_path.clone());
+            }
+            output.textures.push(CombinedTexture {
+                suffix: suffix.clone(),
+                path: output_file_path.clone(),
+            });
+
+            if config.generate_mipmaps && write_outputs {
+                for (level, mip) in generate_mip_chain(image, &owners)?.iter().enumerate() {
+                    let mut mip_file_path = output_file_path.clone();
+                    mip_file_path.set_file_name(format!(
+                        "{}{}_mip{}.png",
+                        &config.output_texture_name.to_string_lossy(),
+                        suffix,
+                        level + 1));
+
+                    let mip_file = mip_file_path.to_str().unwrap();
+                    progress.begin(ProgressStage::Writing, Some(mip_file));
+                    log_info!("{}", mip_file);
+                    write_image_to_file(mip_file, mip, &config.png_options)?;
+                    output.files.push(mip_file_path);
+                }
+            }
+
+            if config.output_layered && write_outputs {
+                // The bottom layer is the unmasked background, every other layer is masked just
+                // like when it was stacked.
+                let layers: Vec<PsdLayer> = layer_images
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (set_index, layer_image))| PsdLayer {
+                        name: &input_sets[*set_index].name,
+                        image: layer_image,
+                        mask: if i == 0 { None } else { Some(&set_masks[*set_index]) },
+                    })
+                    .collect();
+
+                let mut layered_file_path = output_file_path.clone();
+                layered_file_path.set_extension("psd");
+
+                let layered_file = layered_file_path.to_str().unwrap();
+                progress.begin(ProgressStage::Writing, Some(layered_file));
+                log_info!("{}", layered_file);
+                write_psd(layered_file, image, &layers)?;
+                output.files.push(layered_file_path);
+            }
         }
     }
 
-    increment_progress();
+    progress.finish();
+
+    Ok(output)
+}
+
+///
+/// Splits a combined texture back into one texture per set, named `{set}{suffix}.png`. Each output
+/// keeps the pixels inside the set's mask and is transparent everywhere else, so the result can be
+/// stacked again.
+///
+pub(crate) fn split_combined_texture(
+    combined_file: &str,
+    set_masks: &[(String, Vec<bool>)],
+    suffix: &str,
+    output_directory: &Path,
+    png_options: &PngOptions,
+) -> Result<()> {
+    let image = read_image_from_file(combined_file)?;
+    let format = &image.format;
+
+    if format.bit_depth != BitDepth::Eight || !matches!(format.color_type, ColorType::Rgb | ColorType::Rgba) {
+        return Err(format!(
+            "The image '{}' has an unsupported format ({:?}, {:?}), only 8-bit RGB and RGBA images can be split.",
+            combined_file,
+            format.color_type,
+            format.bit_depth).into());
+    }
+
+    let num_pixels = format.width as usize * format.height as usize;
+    let stride = calc_pixel_stride(format)?;
+
+    let output_format = ImageFormat {
+        color_type: ColorType::Rgba,
+        ..*format
+    };
+    let output_stride = calc_pixel_stride(&output_format)?;
+
+    for (name, mask) in set_masks {
+        if mask.len() != num_pixels {
+            return Err(format!(
+                "The mask of texture set '{}' does not have the same resolution as the image '{}' {:?}.",
+                name,
+                combined_file,
+                (format.width, format.height)).into());
+        }
+
+        let mut buffer = vec![0u8; num_pixels * output_stride];
+        for (i, _) in mask.iter().enumerate().filter(|(_, &masked)| masked) {
+            // Alpha is taken from the combined texture if it has one, otherwise the region is opaque.
+            let pixel = bytes_to_pixel(&image.data[i * stride..], format)?;
+            pixel_to_bytes(pixel, &output_format, &mut buffer[i * output_stride..])?;
+        }
+
+        let mut output_file_path = output_directory.to_path_buf();
+        output_file_path.push(format!("{}{}.png", name, suffix));
+
+        let output_file = output_file_path.to_str().unwrap();
+        log_info!("{}", output_file);
+        write_image_to_file(
+            output_file,
+            &RawImage {
+                data: buffer,
+                format: output_format,
+            },
+            png_options,
+        )?;
+    }
 
     Ok(())
 }
 
-fn copy_image_masked(source_image: &RawImage, dest_image: &mut RawImage, mask: &[bool]) {
+///
+/// Reads a black and white mask image. Pixels brighter than mid-gray are inside the mask.
+///
+pub(crate) fn read_mask_from_file(file_name: &str) -> Result<(Vec<bool>, (u32, u32))> {
+    let image = read_image_from_file(file_name)?;
+    let format = &image.format;
+
+    if format.color_type == ColorType::Indexed
+        || !matches!(format.bit_depth, BitDepth::Eight | BitDepth::Sixteen)
+    {
+        return Err(format!(
+            "The mask '{}' has an unsupported format ({:?}, {:?}).",
+            file_name,
+            format.color_type,
+            format.bit_depth).into());
+    }
+
+    let num_pixels = format.width as usize * format.height as usize;
+    if num_pixels == 0 {
+        return Err(format!("The image '{}' is zero sized.", file_name).into());
+    }
+
+    // The first byte of each pixel is the most significant byte of the red or gray sample.
+    let stride = image.data.len() / num_pixels;
+    let mask = image.data
+        .chunks_exact(stride)
+        .map(|pixel| pixel[0] >= 128)
+        .collect();
+
+    Ok((mask, (format.width, format.height)))
+}
+
+fn copy_image_masked(source_image: &RawImage, dest_image: &mut RawImage, mask: &[bool]) -> Result<()> {
     assert_eq!(
         (source_image.format.width, source_image.format.height),
         (dest_image.format.width, dest_image.format.height),
@@ -409,17 +679,19 @@ fn copy_image_masked(source_image: &RawImage, dest_image: &mut RawImage, mask: &
             let pixel = bytes_to_pixel(
                 &source_image.data[i * source_stride..],
                 &source_image.format,
-            );
+            )?;
             pixel_to_bytes(
                 pixel,
                 &dest_image.format,
                 &mut dest_image.data[i * dest_stride..],
-            );
+            )?;
         }
     }
+
+    Ok(())
 }
 
-fn copy_image(source_image: &RawImage, dest_image: &mut RawImage) {
+fn copy_image(source_image: &RawImage, dest_image: &mut RawImage) -> Result<()> {
     if source_image.format == dest_image.format {
         // Fast path: same format, just a memcpy.
         assert_eq!(
@@ -444,12 +716,14 @@ fn copy_image(source_image: &RawImage, dest_image: &mut RawImage) {
             let pixel = bytes_to_pixel(
                 &source_image.data[i * source_stride..],
                 &source_image.format,
-            );
+            )?;
             pixel_to_bytes(
                 pixel,
                 &dest_image.format,
                 &mut dest_image.data[i * dest_stride..],
-            );
+            )?;
         }
     }
+
+    Ok(())
 }
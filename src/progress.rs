@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// What a run is doing, see [Progress].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStage {
+    /// Computing the mask of each texture set.
+    Masks,
+    /// Reading the textures of the sets and stacking them.
+    Combining,
+    /// Writing an output file.
+    Writing,
+    /// Finished with an output.
+    Done,
+}
+
+impl fmt::Display for ProgressStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProgressStage::Masks => "Computing masks",
+            ProgressStage::Combining => "Combining",
+            ProgressStage::Writing => "Writing",
+            ProgressStage::Done => "Done",
+        })
+    }
+}
+
+/// The progress of a run, passed to its progress handler.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// How much of the whole run is done, from 0 to 1.
+    pub fraction: f32,
+    pub stage: ProgressStage,
+    /// The output group being combined.
+    pub output_texture_name: String,
+    /// The file being read or written, if any.
+    pub file: Option<String>,
+}
+
+/// Called whenever the run makes progress or starts on another file.
+pub type ProgressHandler = Box<dyn Fn(&Progress)>;
+
+/// Counts the steps of combining one output and reports them to a progress handler.
+pub(crate) struct ProgressTracker<'a> {
+    handler: Option<&'a dyn Fn(&Progress)>,
+    progress: Progress,
+    steps: f32,
+    total_steps: f32,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(handler: Option<&'a dyn Fn(&Progress)>, output_texture_name: &str, total_steps: usize) -> Self {
+        Self {
+            handler,
+            progress: Progress {
+                fraction: 0.0,
+                stage: ProgressStage::Masks,
+                output_texture_name: output_texture_name.to_owned(),
+                file: None,
+            },
+            steps: 0.0,
+            total_steps: total_steps as f32,
+        }
+    }
+
+    /// Reports that work on a file (or on no file in particular) started.
+    pub fn begin(&mut self, stage: ProgressStage, file: Option<&str>) {
+        self.progress.stage = stage;
+        self.progress.file = file.map(str::to_owned);
+        self.report();
+    }
+
+    /// Reports that one more step is done.
+    pub fn step(&mut self) {
+        self.steps += 1.0;
+        self.progress.fraction = (self.steps / self.total_steps).clamp(0.0, 1.0);
+        self.report();
+    }
+
+    /// Reports that the output is done, even if not every step was counted.
+    pub fn finish(&mut self) {
+        self.progress.fraction = 1.0;
+        self.begin(ProgressStage::Done, None);
+    }
+
+    fn report(&self) {
+        if let Some(handler) = self.handler {
+            handler(&self.progress);
+        }
+    }
+}
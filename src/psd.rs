@@ -0,0 +1,242 @@
+use std::{fs, iter};
+
+use png::{BitDepth, ColorType};
+
+use crate::processing::{ImageFormat, RawImage};
+use crate::Result;
+
+pub(crate) struct PsdLayer<'a> {
+    pub name: &'a str,
+    pub image: &'a RawImage,
+    /// Pixels outside of the mask are hidden by a layer mask.
+    pub mask: Option<&'a [bool]>,
+}
+
+const COLOR_MODE_RGB: u16 = 3;
+const COMPRESSION_RAW: u16 = 0;
+const CHANNEL_TRANSPARENCY: i16 = -1;
+const CHANNEL_USER_MASK: i16 = -2;
+
+///
+/// Writes a layered Photoshop document. `composite` is the flattened result shown by applications
+/// that do not read layers. Layers are given bottom-most first.
+///
+/// All images must be RGB or RGBA with the same resolution and bit depth.
+///
+pub(crate) fn write_psd(file_name: &str, composite: &RawImage, layers: &[PsdLayer]) -> Result<()> {
+    let format = &composite.format;
+    let bytes_per_sample = match format.bit_depth {
+        BitDepth::Eight => 1,
+        BitDepth::Sixteen => 2,
+        _ => {
+            return Err(format!(
+                "Layered output does not support a bit depth of {:?}.",
+                format.bit_depth).into());
+        }
+    };
+
+    for image in layers.iter().map(|layer| layer.image).chain([composite]) {
+        validate_format(&image.format, format)?;
+    }
+
+    let composite_planes = split_planes(composite, bytes_per_sample);
+    let has_alpha = format.color_type == ColorType::Rgba;
+
+    let mut out = Vec::new();
+
+    // File header.
+    out.extend_from_slice(b"8BPS");
+    write_u16(&mut out, 1);
+    out.extend_from_slice(&[0; 6]);
+    write_u16(&mut out, composite_planes.len() as u16);
+    write_u32(&mut out, format.height);
+    write_u32(&mut out, format.width);
+    write_u16(&mut out, bytes_per_sample as u16 * 8);
+    write_u16(&mut out, COLOR_MODE_RGB);
+
+    // Color mode data and image resources, both empty.
+    write_u32(&mut out, 0);
+    write_u32(&mut out, 0);
+
+    // Layer and mask information.
+    let layer_info = write_layer_info(layers, format, bytes_per_sample, has_alpha);
+    write_u32(&mut out, layer_info.len() as u32 + 4 + 4);
+    write_u32(&mut out, layer_info.len() as u32);
+    out.extend_from_slice(&layer_info);
+    // Global layer mask info, empty.
+    write_u32(&mut out, 0);
+
+    // Composite image data.
+    write_u16(&mut out, COMPRESSION_RAW);
+    for plane in &composite_planes {
+        out.extend_from_slice(plane);
+    }
+
+    fs::write(file_name, out)?;
+    Ok(())
+}
+
+fn validate_format(format: &ImageFormat, expected: &ImageFormat) -> Result<()> {
+    if !matches!(format.color_type, ColorType::Rgb | ColorType::Rgba) {
+        return Err(format!(
+            "Layered output does not support the color type {:?}.",
+            format.color_type).into());
+    }
+
+    if (format.width, format.height, format.bit_depth) != (expected.width, expected.height, expected.bit_depth) {
+        return Err("All layers of a layered output must have the same resolution and bit depth.".into());
+    }
+
+    Ok(())
+}
+
+fn write_layer_info(layers: &[PsdLayer], format: &ImageFormat, bytes_per_sample: usize, has_alpha: bool) -> Vec<u8> {
+    let num_pixels = format.width as usize * format.height as usize;
+    let plane_size = num_pixels * bytes_per_sample;
+    let opaque_plane = vec![0xffu8; plane_size];
+
+    let mut records = Vec::new();
+    let mut channel_data = Vec::new();
+
+    for layer in layers {
+        let planes = split_planes(layer.image, bytes_per_sample);
+
+        // The layer's own alpha is only used if the output keeps its alpha channel, otherwise the
+        // layer is opaque just like the composite.
+        let transparency = match (has_alpha, planes.get(3)) {
+            (true, Some(alpha)) => alpha,
+            _ => &opaque_plane,
+        };
+
+        let mut channels: Vec<(i16, &[u8])> = vec![
+            (CHANNEL_TRANSPARENCY, transparency),
+            (0, &planes[0]),
+            (1, &planes[1]),
+            (2, &planes[2]),
+        ];
+
+        let mask_plane = layer.mask.map(|mask| {
+            assert_eq!(mask.len(), num_pixels, "mask size does not match image size");
+            mask.iter()
+                .flat_map(|&visible| iter::repeat_n(if visible { 0xff } else { 0 }, bytes_per_sample))
+                .collect::<Vec<u8>>()
+        });
+        if let Some(mask_plane) = &mask_plane {
+            channels.push((CHANNEL_USER_MASK, mask_plane));
+        }
+
+        // Layer record.
+        write_rect(&mut records, format);
+        write_u16(&mut records, channels.len() as u16);
+        for (id, data) in &channels {
+            records.extend_from_slice(&id.to_be_bytes());
+            write_u32(&mut records, 2 + data.len() as u32);
+        }
+        records.extend_from_slice(b"8BIM");
+        records.extend_from_slice(b"norm");
+        records.push(255); // opacity
+        records.push(0); // clipping
+        records.push(0); // flags
+        records.push(0); // filler
+
+        let mut extra = Vec::new();
+        if mask_plane.is_some() {
+            write_u32(&mut extra, 20);
+            write_rect(&mut extra, format);
+            extra.push(0); // default color
+            extra.push(0); // flags
+            extra.extend_from_slice(&[0; 2]);
+        } else {
+            write_u32(&mut extra, 0);
+        }
+        // Blending ranges, empty.
+        write_u32(&mut extra, 0);
+        write_pascal_name(&mut extra, layer.name);
+        write_unicode_name(&mut extra, layer.name);
+
+        write_u32(&mut records, extra.len() as u32);
+        records.extend_from_slice(&extra);
+
+        for (_, data) in &channels {
+            write_u16(&mut channel_data, COMPRESSION_RAW);
+            channel_data.extend_from_slice(data);
+        }
+    }
+
+    let mut info = Vec::new();
+    write_u16(&mut info, layers.len() as u16);
+    info.extend_from_slice(&records);
+    info.extend_from_slice(&channel_data);
+    if info.len() % 2 != 0 {
+        info.push(0);
+    }
+
+    info
+}
+
+/// Splits interleaved pixel data into one plane per channel.
+fn split_planes(image: &RawImage, bytes_per_sample: usize) -> Vec<Vec<u8>> {
+    let channels = match image.format.color_type {
+        ColorType::Rgba => 4,
+        _ => 3,
+    };
+    let stride = channels * bytes_per_sample;
+
+    (0..channels)
+        .map(|channel| {
+            image.data
+                .chunks_exact(stride)
+                .flat_map(|pixel| &pixel[channel * bytes_per_sample..][..bytes_per_sample])
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+fn write_rect(out: &mut Vec<u8>, format: &ImageFormat) {
+    // top, left, bottom, right
+    write_u32(out, 0);
+    write_u32(out, 0);
+    write_u32(out, format.height);
+    write_u32(out, format.width);
+}
+
+/// Writes the legacy layer name: a pascal string padded to a multiple of 4 bytes.
+fn write_pascal_name(out: &mut Vec<u8>, name: &str) {
+    let bytes: Vec<u8> = name
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .take(255)
+        .collect();
+
+    let padded_len = (bytes.len() + 1).div_ceil(4) * 4;
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(&bytes);
+    out.resize(out.len() + padded_len - (bytes.len() + 1), 0);
+}
+
+/// Writes the unicode layer name as additional layer information ('luni').
+fn write_unicode_name(out: &mut Vec<u8>, name: &str) {
+    let chars: Vec<u16> = name.encode_utf16().collect();
+    let mut data = Vec::new();
+    write_u32(&mut data, chars.len() as u32);
+    for c in chars {
+        write_u16(&mut data, c);
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+
+    out.extend_from_slice(b"8BIM");
+    out.extend_from_slice(b"luni");
+    write_u32(out, data.len() as u32);
+    out.extend_from_slice(&data);
+}
+
+fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// What happened during a [crate::run].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    /// The combined outputs written, one per output group.
+    pub outputs: Vec<OutputReport>,
+    /// Texture sets that were found but left out by the include/exclude filters.
+    pub excluded_sets: Vec<ExcludedSet>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludedSet {
+    pub name: String,
+    /// Why the set was left out, e.g. `matches 'LOD*' in exclude_sets`.
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputReport {
+    pub output_texture_name: String,
+    /// The texture sets combined into this output, in compositing order: the first set is the
+    /// background and later sets are drawn over earlier ones.
+    pub sets: Vec<String>,
+    /// Which texture types each set in `sets` has.
+    pub coverage: Vec<SetCoverage>,
+    /// The combined texture of each texture type.
+    pub textures: Vec<PathBuf>,
+    /// Every file written for this output, including mipmaps, layered files, the ID map and the
+    /// merged glTF. Empty when validating.
+    pub files: Vec<PathBuf>,
+    /// How long combining this output took.
+    pub seconds: f32,
+}
+
+/// The texture types of a texture set, by their suffix.
+#[derive(Debug, Clone, Serialize)]
+pub struct SetCoverage {
+    pub name: String,
+    pub texture_types: Vec<String>,
+    pub missing_types: Vec<String>,
+}
@@ -1,43 +1,105 @@
-use std::path::Path;
-
-pub fn suffix_from_filename(filename: &str) -> Option<&str> {
-    if let Some(stem) = Path::new(filename).file_stem() {
-        let stem = stem.to_str().unwrap();
-        if let Some(pos) = stem.rfind('_') {
-            return Some(&stem[pos..]);
-        }
-    }
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-    None
-}
+use serde::Serialize;
 
 macro_rules! log_info {
     ($fmt:literal) => {
-        std::println!($fmt);
+        $crate::util::print_info(std::format!($fmt))
     };
     ($fmt:literal, $($arg:tt)*) => {
-        std::println!($fmt, $($arg)*);
+        $crate::util::print_info(std::format!($fmt, $($arg)*))
     };
 }
 
 macro_rules! log_warn {
     ($fmt:literal) => {
-        std::eprintln!(std::concat!("\x1b[33m[WARN]\x1b[0m ", $fmt));
+        $crate::util::record_warning(std::format!($fmt))
     };
     ($fmt:literal, $($arg:tt)*) => {
-        std::eprintln!(std::concat!("\x1b[33m[WARN]\x1b[0m ", $fmt), $($arg)*);
+        $crate::util::record_warning(std::format!($fmt, $($arg)*))
     };
 }
 
 macro_rules! log_error {
     ($fmt:literal) => {
-        std::eprintln!(std::concat!("\x1b[31m[ERROR]\x1b[0m ", $fmt));
+        $crate::util::record_error(std::format!($fmt))
     };
     ($fmt:literal, $($arg:tt)*) => {
-        std::eprintln!(std::concat!("\x1b[31m[ERROR]\x1b[0m ", $fmt), $($arg)*);
+        $crate::util::record_error(std::format!($fmt, $($arg)*))
     };
 }
 
 pub(crate) use log_error;
 pub(crate) use log_info;
 pub(crate) use log_warn;
+
+/// Whether log messages are printed, see [set_log_printing].
+static PRINT_LOG: AtomicBool = AtomicBool::new(true);
+
+thread_local! {
+    /// The warnings and errors logged on this thread that have not been taken yet.
+    static LOG_MESSAGES: RefCell<LogMessages> = RefCell::new(LogMessages::default());
+    /// Whether log messages on this thread are dropped, see [without_logging].
+    static IS_MUTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Warnings and errors that were logged, see [take_log_messages].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LogMessages {
+    pub warnings: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Enables or disables printing log messages to stdout and stderr. Warnings and errors are still
+/// recorded when printing is disabled.
+pub fn set_log_printing(enabled: bool) {
+    PRINT_LOG.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the warnings and errors logged on the current thread since the last call.
+pub fn take_log_messages() -> LogMessages {
+    LOG_MESSAGES.with(|messages| messages.take())
+}
+
+/// Runs `f` without printing or recording anything it logs on the current thread.
+pub(crate) fn without_logging<T>(f: impl FnOnce() -> T) -> T {
+    let was_muted = IS_MUTED.with(|muted| muted.replace(true));
+    let result = f();
+    IS_MUTED.with(|muted| muted.set(was_muted));
+    result
+}
+
+fn is_muted() -> bool {
+    IS_MUTED.with(Cell::get)
+}
+
+fn is_printing_log() -> bool {
+    PRINT_LOG.load(Ordering::Relaxed)
+}
+
+pub(crate) fn print_info(message: String) {
+    if is_printing_log() && !is_muted() {
+        println!("{}", message);
+    }
+}
+
+pub(crate) fn record_warning(message: String) {
+    if is_muted() {
+        return;
+    }
+    if is_printing_log() {
+        eprintln!("\x1b[33m[WARN]\x1b[0m {}", message);
+    }
+    LOG_MESSAGES.with(|messages| messages.borrow_mut().warnings.push(message));
+}
+
+pub(crate) fn record_error(message: String) {
+    if is_muted() {
+        return;
+    }
+    if is_printing_log() {
+        eprintln!("\x1b[31m[ERROR]\x1b[0m {}", message);
+    }
+    LOG_MESSAGES.with(|messages| messages.borrow_mut().errors.push(message));
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::filter::IGNORE_FILE_NAME;
+use crate::processing::OutputMode;
+use crate::util::{log_info, log_warn, without_logging};
+use crate::{
+    collect_png_files, discover_texture_sets, get_input_directories, get_manifest_path, get_output_directory,
+    run_with_output_mode, Config, Progress, ProgressHandler, Result, RunReport,
+};
+
+/// How often the input files are checked and how long to wait for writes to settle.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Time between checking the modification times of the input files.
+    pub poll_interval: Duration,
+    /// How long the input files must stay unchanged before rebuilding, so a burst of writes from
+    /// an export causes only one rebuild.
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            debounce: Duration::from_secs(1),
+        }
+    }
+}
+
+/// One build done by [watch].
+#[derive(Debug, Clone)]
+pub struct Rebuild {
+    /// The files that changed since the previous build, empty for the first build.
+    pub changed_files: Vec<PathBuf>,
+    /// The texture types that were combined again.
+    pub suffixes: Vec<String>,
+    /// Whether every texture type and extra output was built, rather than only `suffixes`.
+    pub full: bool,
+    pub result: std::result::Result<RunReport, String>,
+    pub seconds: f32,
+}
+
+impl fmt::Display for Rebuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Err(err) = &self.result {
+            return write!(f, "Rebuild failed: {}", err);
+        }
+
+        let changed = match self.changed_files.len() {
+            1 => "1 changed file".to_owned(),
+            count => format!("{} changed files", count),
+        };
+        if self.changed_files.is_empty() {
+            write!(f, "Built everything")?;
+        } else if self.full {
+            write!(f, "Rebuilt everything after {}", changed)?;
+        } else {
+            write!(f, "Rebuilt {} after {}", self.suffixes.join(", "), changed)?;
+        }
+        write!(f, " in {:.2} s.", self.seconds)
+    }
+}
+
+/// The files a build depends on and when they were last modified.
+struct WatchedFiles {
+    modified: BTreeMap<PathBuf, Option<SystemTime>>,
+    /// The texture type of each texture used in the last build.
+    texture_types: BTreeMap<PathBuf, String>,
+    /// Files that change the set masks or which sets there are when modified: the ID map, mesh,
+    /// manifest and ignore file.
+    structure_files: BTreeSet<PathBuf>,
+}
+
+impl WatchedFiles {
+    fn scan(config: &Config) -> Result<Self> {
+        let input_directories = get_input_directories(config)?;
+        let input_directory = &input_directories[0];
+        let excluded_directory = get_output_directory(config, input_directory).canonicalize().ok();
+
+        let mut files = Vec::new();
+        for directory in &input_directories {
+            collect_png_files(directory, config.scan_depth, excluded_directory.as_deref(), &mut files)?;
+        }
+
+        let mut structure_files = BTreeSet::from([input_directory.join(IGNORE_FILE_NAME)]);
+        structure_files.extend(get_manifest_path(config));
+        if let Some(id_map) = &config.id_map {
+            structure_files.insert(input_directory.join(&id_map.texture));
+            structure_files.extend(id_map.legend.as_ref().map(|legend| input_directory.join(legend)));
+        }
+        if let Some(mesh_masks) = &config.mesh_masks {
+            structure_files.insert(input_directory.join(&mesh_masks.mesh));
+        }
+
+        // A config that doesn't build right now is still watched, every change then rebuilds
+        // everything. The warnings of the sets are reported by the builds, not on every poll.
+        let mut texture_types = BTreeMap::new();
+        if let Ok(discovered) = without_logging(|| discover_texture_sets(config)) {
+            for set in &discovered.sets {
+                for (suffix, texture) in config.suffixes.iter().zip(&set.textures) {
+                    if let Some(texture) = texture {
+                        texture_types.insert(PathBuf::from(texture), suffix.clone());
+                    }
+                }
+            }
+        }
+
+        let modified = files
+            .into_iter()
+            .chain(structure_files.iter().cloned())
+            .chain(texture_types.keys().cloned())
+            .map(|path| {
+                let modified = modified_time(&path);
+                (path, modified)
+            })
+            .collect();
+
+        Ok(Self {
+            modified,
+            texture_types,
+            structure_files,
+        })
+    }
+
+    /// Returns the files that were added, removed or modified compared to `previous`.
+    fn changes_since(&self, previous: &WatchedFiles) -> BTreeSet<PathBuf> {
+        let paths: BTreeSet<&PathBuf> = self.modified.keys().chain(previous.modified.keys()).collect();
+        paths
+            .into_iter()
+            .filter(|path| self.modified.get(*path).copied().flatten() != previous.modified.get(*path).copied().flatten())
+            .cloned()
+            .collect()
+    }
+
+    ///
+    /// Returns the texture types that need to be combined again after `changed_files` changed, or
+    /// `None` if everything needs to be rebuilt: when files were added or removed, the masks
+    /// changed or a changed file was not used by the last build.
+    ///
+    fn affected_suffixes(&self, changed_files: &BTreeSet<PathBuf>, config: &Config) -> Option<Vec<String>> {
+        let uses_alpha_mask = config.id_map.is_none() && config.mesh_masks.is_none();
+        let mut suffixes = BTreeSet::new();
+
+        for path in changed_files {
+            if self.structure_files.contains(path) || modified_time(path).is_none() {
+                return None;
+            }
+
+            let suffix = self.texture_types.get(path)?;
+            if uses_alpha_mask && suffix == &config.suffixes[0] {
+                return None;
+            }
+            suffixes.insert(suffix);
+        }
+
+        // Keep the order of the config.
+        Some(config.suffixes.iter().filter(|suffix| suffixes.contains(suffix)).cloned().collect())
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|metadata| metadata.modified()).ok()
+}
+
+///
+/// Builds the config, then keeps watching its input files and builds again whenever they change,
+/// until `stop` is set. When only textures of some texture types changed, only those are combined
+/// again. `on_rebuild` is called after every build, also when it failed.
+///
+/// The input directories are polled, so this works the same on every platform and network drive.
+///
+pub fn watch(
+    config: &Config,
+    options: &WatchOptions,
+    stop: &AtomicBool,
+    progress_handler: Option<ProgressHandler>,
+    mut on_rebuild: impl FnMut(&Rebuild),
+) -> Result<()> {
+    let mut config = config.clone();
+    // Opening the output directory after every build would get in the way.
+    config.open_output_directory = false;
+
+    let progress_handler = progress_handler.map(Rc::<dyn Fn(&Progress)>::from);
+    let mut build = |changed_files: Vec<PathBuf>, suffixes: Option<Vec<String>>| {
+        let start_time = Instant::now();
+        let handler = progress_handler.clone().map(|handler| {
+            Box::new(move |progress: &Progress| handler(progress)) as ProgressHandler
+        });
+        let result = run_with_output_mode(&config, handler, OutputMode::All, suffixes.as_deref());
+
+        on_rebuild(&Rebuild {
+            changed_files,
+            full: suffixes.is_none(),
+            suffixes: suffixes.unwrap_or_else(|| config.suffixes.clone()),
+            result: result.map_err(|err| err.to_string()),
+            seconds: start_time.elapsed().as_secs_f32(),
+        });
+    };
+
+    // The files as they were when the last build started.
+    let mut watched = WatchedFiles::scan(&config)?;
+    build(Vec::new(), None);
+    // Whether the last scan failed, so a failure that keeps on going is only reported once.
+    let mut scan_failing = false;
+
+    loop {
+        // Wait for a change, then until the files stay the same for the debounce time.
+        let mut latest: Option<WatchedFiles> = None;
+        let mut last_change = Instant::now();
+
+        loop {
+            thread::sleep(options.poll_interval);
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+
+            let next = match WatchedFiles::scan(&config) {
+                Ok(next) => next,
+                Err(err) => {
+                    if !scan_failing {
+                        log_warn!("Unable to check the input files for changes: {}", err);
+                        scan_failing = true;
+                    }
+                    continue;
+                }
+            };
+            if scan_failing {
+                log_info!("The input files can be checked for changes again.");
+                scan_failing = false;
+            }
+
+            if !next.changes_since(latest.as_ref().unwrap_or(&watched)).is_empty() {
+                latest = Some(next);
+                last_change = Instant::now();
+            } else if latest.is_some() && last_change.elapsed() >= options.debounce {
+                break;
+            }
+        }
+
+        // The files may have been changed and then restored.
+        let latest = latest.expect("the files changed");
+        let changed_files = latest.changes_since(&watched);
+        if changed_files.is_empty() {
+            continue;
+        }
+
+        let suffixes = watched.affected_suffixes(&changed_files, &config);
+        watched = latest;
+        build(changed_files.into_iter().collect(), suffixes);
+    }
+}